@@ -172,6 +172,8 @@ def main():
         write_sparse_vectors_to_binary_file_2(seismic_format_doc_path, documents)
         seismic_format_query_path = os.path.join(data_dir, "queries.bin")
         write_sparse_vectors_to_binary_file(seismic_format_query_path, queries)
+        with open(os.path.join(data_dir, "vocabulary.json"), "w") as f:
+            json.dump(token_id_mapping, f)
         
     elif args.input_format == "nq":
         
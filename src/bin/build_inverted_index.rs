@@ -1,16 +1,33 @@
+use seismic::build_observer::TerminalObserver;
+use seismic::compressed_dataset::ValueQuantization;
 use seismic::inverted_index::{
-    BlockingStrategy, Configuration, PruningStrategy, SummarizationStrategy,
+    BlockOrder, BlockingStrategy, Configuration, ForwardIndexStrategy, PostingsStrategy,
+    PruningStrategy, SummarizationStrategy,
 };
-use seismic::{InvertedIndex, SparseDataset};
+use seismic::{InvertedIndex, SpaceUsage, SparseDataset};
 
 use std::fs;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::time::Instant;
 
 // TODO:
 // - add control to the Rayon's number of threads
 
+#[derive(Clone, Debug, ValueEnum)]
+enum PruningArg {
+    /// Keeps the top `n_postings` postings of each posting list.
+    Fixed,
+    /// Keeps on average `n_postings` postings per list with a global threshold, at most `max_fraction * n_postings` per list.
+    GlobalThreshold,
+    /// Keeps the top postings of each list until `pruning_energy` of the scores of the list is covered.
+    Energy,
+    /// Keeps the postings whose score is at least `pruning_threshold`.
+    Threshold,
+    /// Keeps every document only in the posting lists of its top `doc_components` components.
+    DocumentCentric,
+}
+
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
@@ -27,6 +44,31 @@ struct Args {
     #[arg(default_value_t = 6000)]
     n_postings: usize,
 
+    /// The strategy used to prune the posting lists.
+    #[clap(long, value_enum)]
+    #[arg(default_value = "global-threshold")]
+    pruning: PruningArg,
+
+    /// Limits the length of each posting list to `max_fraction * n_postings` with `global-threshold` pruning.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = 1.5)]
+    max_fraction: f32,
+
+    /// The fraction of the scores of each posting list preserved by `energy` pruning.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = 0.9)]
+    pruning_energy: f32,
+
+    /// The minimum score of a posting kept by `threshold` pruning.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = 0.0)]
+    pruning_threshold: f32,
+
+    /// The number of components of each document kept by `document-centric` pruning.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = 64)]
+    doc_components: usize,
+
     /// Block size in the fixed size blockin
     #[clap(short, long, value_parser)]
     #[arg(default_value_t = 10)]
@@ -52,14 +94,41 @@ struct Args {
     #[clap(short, long, value_parser)]
     #[arg(default_value_t = 2)]
     min_cluster_size: usize,
+
+    /// Compresses the forward index by quantizing its values with the given number of bits (8 or 4). The forward index is not compressed if omitted.
+    #[clap(long, value_parser)]
+    forward_bits: Option<u8>,
+
+    /// Compresses the postings of each block by gap-encoding their sorted doc ids.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = false)]
+    compress_postings: bool,
+
+    /// Stores the original-precision vectors in the index to rerank the candidates at query time.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = false)]
+    rerank: bool,
+
+    /// Sorts the blocks of each posting list by decreasing largest summary value.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = false)]
+    order_blocks: bool,
+
+    /// A dataset file with a sample of the queries. If given, frequently queried components get more postings and finer blocks.
+    #[clap(long, value_parser)]
+    query_log: Option<String>,
+
+    /// The maximum size of the index in MiB. If given, the number of postings is chosen to fit this budget instead of `n_postings`.
+    #[clap(long, value_parser)]
+    budget_mib: Option<f64>,
 }
 
 pub fn main() {
     let args = Args::parse();
 
-    let dataset = SparseDataset::<f32>::read_bin_file(&args.input_file.unwrap())
-        .unwrap()
-        .quantize_f16();
+    let original_dataset = SparseDataset::<f32>::read_bin_file(&args.input_file.unwrap()).unwrap();
+    let rerank_dataset = args.rerank.then(|| original_dataset.clone());
+    let dataset = original_dataset.quantize_f16();
 
     println!("Number of Vectors: {}", dataset.len());
     println!("Number of Dimensions: {}", dataset.dim());
@@ -69,13 +138,40 @@ pub fn main() {
         dataset.nnz() as f32 / dataset.len() as f32
     );
 
+    let forward_index = match args.forward_bits {
+        None => ForwardIndexStrategy::Uncompressed,
+        Some(8) => ForwardIndexStrategy::Compressed {
+            quantization: ValueQuantization::Bits8,
+        },
+        Some(4) => ForwardIndexStrategy::Compressed {
+            quantization: ValueQuantization::Bits4,
+        },
+        Some(bits) => panic!("Forward index values can be quantized with 8 or 4 bits, not {bits}"),
+    };
+
     let time = Instant::now();
 
-    let config = Configuration::default()
-        .pruning_strategy(PruningStrategy::GlobalThreshold {
+    let pruning = match args.pruning {
+        PruningArg::Fixed => PruningStrategy::FixedSize {
             n_postings: args.n_postings,
-            max_fraction: 1.5,
-        })
+        },
+        PruningArg::GlobalThreshold => PruningStrategy::GlobalThreshold {
+            n_postings: args.n_postings,
+            max_fraction: args.max_fraction,
+        },
+        PruningArg::Energy => PruningStrategy::EnergyPreserving {
+            fraction: args.pruning_energy,
+        },
+        PruningArg::Threshold => PruningStrategy::Threshold {
+            threshold: args.pruning_threshold,
+        },
+        PruningArg::DocumentCentric => PruningStrategy::DocumentCentric {
+            n_components: args.doc_components,
+        },
+    };
+
+    let config = Configuration::default()
+        .pruning_strategy(pruning)
         .blocking_strategy(BlockingStrategy::RandomKmeans {
             centroid_fraction: args.centroid_fraction,
             truncated_kmeans_training: args.truncation,
@@ -84,11 +180,45 @@ pub fn main() {
         })
         .summarization_strategy(SummarizationStrategy::EnergyPerserving {
             summary_energy: args.summary_energy,
+        })
+        .forward_index_strategy(forward_index)
+        .postings_strategy(if args.compress_postings {
+            PostingsStrategy::Compressed
+        } else {
+            PostingsStrategy::Packed
+        })
+        .block_order(if args.order_blocks {
+            BlockOrder::MaxSummaryWeight
+        } else {
+            BlockOrder::Clustering
         });
     println!("\nBuilding the index...");
     println!("{:?}", config);
 
-    let inverted_index = InvertedIndex::build(dataset, config);
+    let observer = TerminalObserver::new();
+    let mut inverted_index = match (args.query_log, args.budget_mib) {
+        (Some(_), Some(_)) => panic!("A query log and a memory budget cannot be used together"),
+        (Some(query_log), None) => {
+            let query_log = SparseDataset::<f32>::read_bin_file(&query_log).unwrap();
+            InvertedIndex::build_with_query_log_with_observer(
+                dataset, config, &query_log, &observer,
+            )
+        }
+        (None, Some(budget_mib)) => {
+            // The rerank dataset is stored in the index as well, so it takes part of the budget.
+            let rerank_byte = rerank_dataset.as_ref().map_or(0, |d| d.space_usage_byte());
+            let budget_byte = ((budget_mib * 1024.0 * 1024.0) as usize)
+                .checked_sub(rerank_byte)
+                .expect("The rerank dataset alone exceeds the memory budget");
+            InvertedIndex::build_with_budget_with_observer(dataset, config, budget_byte, &observer)
+        }
+        (None, None) => InvertedIndex::build_with_observer(dataset, config, &observer),
+    }
+    .expect("The terminal observer never cancels the construction");
+
+    if let Some(rerank_dataset) = rerank_dataset {
+        inverted_index = inverted_index.with_rerank_dataset(rerank_dataset);
+    }
 
     let elapsed = time.elapsed();
     println!(
@@ -0,0 +1,35 @@
+use std::fs;
+
+use half::f16;
+use seismic::InvertedIndex;
+
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// The path of the index.
+    #[clap(short, long, value_parser)]
+    index_file: String,
+
+    /// Prints the statistics as a single line of JSON instead of indented JSON.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = false)]
+    compact: bool,
+}
+
+pub fn main() {
+    let args = Args::parse();
+
+    let serialized: Vec<u8> = fs::read(&args.index_file).unwrap();
+    let inverted_index = bincode::deserialize::<InvertedIndex<f16>>(&serialized).unwrap();
+
+    let stats = inverted_index.stats();
+
+    let json = if args.compact {
+        serde_json::to_string(&stats)
+    } else {
+        serde_json::to_string_pretty(&stats)
+    };
+    println!("{}", json.unwrap());
+}
@@ -5,9 +5,32 @@ use std::io::Write;
 use std::time::Instant;
 
 use half::f16;
+use seismic::inverted_index::{BlockScan, BlockTraversal, QueryPruning, SearchConfiguration};
 use seismic::{InvertedIndex, SparseDataset};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+
+#[derive(Clone, Debug, ValueEnum)]
+enum QueryPruningArg {
+    /// Keeps the top `query_cut` components.
+    Fixed,
+    /// Keeps components until `query_energy` of the L1 norm of the query is covered.
+    L1,
+    /// Keeps components until `query_energy` of the squared L2 norm of the query is covered.
+    L2,
+    /// Keeps the components whose value is at least `min_query_value`.
+    MinValue,
+}
+
+#[derive(Clone, Debug, ValueEnum)]
+enum BlockScanArg {
+    /// Tests every block of a posting list against the threshold.
+    Full,
+    /// Visits the blocks by decreasing estimated dot product and stops at the first one below the threshold.
+    Sorted,
+    /// Visits the blocks in storage order and stops at the first one below the threshold. Meant for indexes built with `--order-blocks`.
+    EarlyBreak,
+}
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -39,15 +62,53 @@ struct Args {
     #[arg(default_value_t = 1)]
     n_runs: usize,
 
+    /// The strategy used to select the components of the query whose posting lists are evaluated.
+    #[clap(long, value_enum)]
+    #[arg(default_value = "fixed")]
+    query_pruning: QueryPruningArg,
+
     /// A paramenter that trade-off efficiency and accuracy. The search algorithm will only consider the top `query_cut` components of the query.
     #[clap(long, value_parser)]
     #[arg(default_value_t = 10)]
     query_cut: usize,
 
+    /// The fraction of the norm of the query covered by the selected components with `l1` and `l2` query pruning.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = 0.9)]
+    query_energy: f32,
+
+    /// The minimum value of a selected component with `min-value` query pruning.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = 0.0)]
+    min_query_value: f32,
+
     /// A parameter that trade-off efficiency and accuracy. The search algorithm will skip a block which estimated dot product is greater than `heap_factor` times the smallest dot product of the top-k results in the current heap.
     #[clap(long, value_parser)]
     #[arg(default_value_t = 0.7)]
     heap_factor: f32,
+
+    /// Evaluates the blocks of all the selected posting lists by decreasing estimated dot product, instead of one list after the other.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = false)]
+    best_first: bool,
+
+    /// How the blocks of each posting list are scanned.
+    #[clap(long, value_enum)]
+    #[arg(default_value = "full")]
+    block_scan: BlockScanArg,
+
+    /// Stops visiting posting lists as soon as their upper bounds cannot improve the current top-k.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = false)]
+    dynamic_termination: bool,
+
+    /// If set, the search collects `n_candidates` results and reranks them with the original-precision vectors. The index must store them, or they must be given with `--rerank-file`.
+    #[clap(long, value_parser)]
+    n_candidates: Option<usize>,
+
+    /// The dataset file with the original-precision vectors used to rerank the candidates.
+    #[clap(long, value_parser)]
+    rerank_file: Option<String>,
 }
 
 pub fn main() {
@@ -61,7 +122,43 @@ pub fn main() {
 
     let serialized: Vec<u8> = fs::read(index_path.unwrap()).unwrap();
 
-    let inverted_index = bincode::deserialize::<InvertedIndex<f16>>(&serialized).unwrap();
+    let mut inverted_index = bincode::deserialize::<InvertedIndex<f16>>(&serialized).unwrap();
+
+    if let Some(rerank_file) = args.rerank_file {
+        let rerank_dataset = SparseDataset::<f32>::read_bin_file(&rerank_file).unwrap();
+        inverted_index = inverted_index.with_rerank_dataset(rerank_dataset);
+    }
+
+    let query_pruning = match args.query_pruning {
+        QueryPruningArg::Fixed => QueryPruning::FixedSize {
+            n_components: query_cut,
+        },
+        QueryPruningArg::L1 => QueryPruning::L1Energy {
+            fraction: args.query_energy,
+        },
+        QueryPruningArg::L2 => QueryPruning::L2Energy {
+            fraction: args.query_energy,
+        },
+        QueryPruningArg::MinValue => QueryPruning::MinValue {
+            threshold: args.min_query_value,
+        },
+    };
+
+    let search_config = SearchConfiguration::default()
+        .query_pruning(query_pruning)
+        .heap_factor(heap_factor)
+        .traversal(if args.best_first {
+            BlockTraversal::BestFirst
+        } else {
+            BlockTraversal::ListByList
+        })
+        .block_scan(match args.block_scan {
+            BlockScanArg::Full => BlockScan::Full,
+            BlockScanArg::Sorted => BlockScan::SortedBySummary,
+            BlockScanArg::EarlyBreak => BlockScan::EarlyBreak,
+        })
+        .dynamic_termination(args.dynamic_termination)
+        .rerank(args.n_candidates);
 
     let queries = SparseDataset::<f32>::read_bin_file(&query_path.unwrap()).unwrap();
 
@@ -86,7 +183,7 @@ pub fn main() {
         results.clear();
         for (query_id, (q_components, q_values)) in queries.iter().take(n_queries).enumerate() {
             let cur_results =
-                inverted_index.search(q_components, q_values, args.k, query_cut, heap_factor);
+                inverted_index.search_with_config(q_components, q_values, args.k, &search_config);
 
             if cur_results.len() < args.k {
                 println!(
@@ -0,0 +1,733 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use half::f16;
+use seismic::inverted_index::SearchConfiguration;
+use seismic::{IndexHandle, SpaceUsage};
+
+use clap::Parser;
+use rayon::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// The path of the index.
+    #[clap(short, long, value_parser)]
+    index_file: String,
+
+    /// A JSON file mapping each token to its component. Required to search queries given as a `vector` of token weights.
+    #[clap(short, long, value_parser)]
+    vocabulary: Option<String>,
+
+    /// The address the server listens on.
+    #[clap(short, long, value_parser)]
+    #[arg(default_value = "127.0.0.1:8000")]
+    address: String,
+
+    /// The number of requests served concurrently. Defaults to the number of available cores.
+    #[clap(short, long, value_parser)]
+    n_workers: Option<usize>,
+
+    /// The number of top-k results returned when a request does not give `k`.
+    #[clap(short, long, value_parser)]
+    #[arg(default_value_t = 10)]
+    k: usize,
+
+    /// The `query_cut` used when a request does not give it.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = 10)]
+    query_cut: usize,
+
+    /// The `heap_factor` used when a request does not give it.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = 0.7)]
+    heap_factor: f32,
+
+    /// The directory of the index files that `/reload` may load. Reloading is disabled without it.
+    #[clap(long, value_parser)]
+    reload_dir: Option<String>,
+
+    /// The largest accepted request body, in MiB. Larger requests are rejected.
+    #[clap(long, value_parser)]
+    #[arg(default_value_t = 16)]
+    max_body_mib: usize,
+}
+
+/// A sparse query, given either as parallel `components` and `values` arrays
+/// or as a `vector` mapping tokens to their weights.
+#[derive(Deserialize, Debug)]
+struct Query {
+    components: Option<Vec<u16>>,
+    values: Option<Vec<f32>>,
+    vector: Option<HashMap<String, f32>>,
+}
+
+/// The search parameters of a request. Missing ones take the defaults of the server.
+#[derive(Deserialize, Debug)]
+struct SearchParams {
+    k: Option<usize>,
+    query_cut: Option<usize>,
+    heap_factor: Option<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchRequest {
+    #[serde(flatten)]
+    query: Query,
+    #[serde(flatten)]
+    params: SearchParams,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchSearchRequest {
+    queries: Vec<Query>,
+    #[serde(flatten)]
+    params: SearchParams,
+}
+
+/// The file of the new index, relative to the reload directory of the server.
+#[derive(Deserialize, Debug)]
+struct ReloadRequest {
+    index_file: String,
+}
+
+/// The state of the reloads, which run in a background thread.
+#[derive(Default, Debug)]
+struct ReloadStatus {
+    running: bool,
+    last_error: Option<String>,
+}
+
+/// A request which cannot be served, with its HTTP status code.
+#[derive(Debug)]
+struct RequestError {
+    status: u16,
+    message: String,
+}
+
+impl RequestError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: 400,
+            message: message.into(),
+        }
+    }
+
+    fn forbidden(message: impl Into<String>) -> Self {
+        Self {
+            status: 403,
+            message: message.into(),
+        }
+    }
+}
+
+/// The state shared by all the workers of the server.
+struct SearchService {
+    index: Arc<IndexHandle<f16>>,
+    vocabulary: Option<HashMap<String, u16>>,
+    // The canonical path of the directory of the files that can be reloaded, if reloading is enabled.
+    reload_dir: Option<PathBuf>,
+    reload_status: Arc<Mutex<ReloadStatus>>,
+    k: usize,
+    query_cut: usize,
+    heap_factor: f32,
+    start: Instant,
+    n_requests: AtomicUsize,
+    n_queries: AtomicUsize,
+}
+
+impl SearchService {
+    fn new(
+        index: IndexHandle<f16>,
+        vocabulary: Option<HashMap<String, u16>>,
+        reload_dir: Option<PathBuf>,
+        k: usize,
+        query_cut: usize,
+        heap_factor: f32,
+    ) -> Self {
+        Self {
+            index: Arc::new(index),
+            vocabulary,
+            reload_dir,
+            reload_status: Arc::new(Mutex::new(ReloadStatus::default())),
+            k,
+            query_cut,
+            heap_factor,
+            start: Instant::now(),
+            n_requests: AtomicUsize::new(0),
+            n_queries: AtomicUsize::new(0),
+        }
+    }
+
+    /// Serves a request and returns its status code and JSON body.
+    fn handle(&self, method: &Method, url: &str, body: &str) -> (u16, Value) {
+        self.n_requests.fetch_add(1, Ordering::Relaxed);
+
+        let path = url.split('?').next().unwrap_or_default();
+        let ok = |value| (200, value);
+        let result = match (method, path) {
+            (Method::Get, "/health") => Ok(ok(json!({ "status": "ok" }))),
+            (Method::Get, "/stats") => Ok(ok(self.stats())),
+            (Method::Post, "/search") => self.search(body).map(ok),
+            (Method::Post, "/batch_search") => self.batch_search(body).map(ok),
+            (Method::Post, "/reload") => self.reload(body).map(|value| (202, value)),
+            (_, "/health" | "/stats" | "/search" | "/batch_search" | "/reload") => {
+                Err(RequestError {
+                    status: 405,
+                    message: format!("Method {method} is not allowed on {path}"),
+                })
+            }
+            _ => Err(RequestError {
+                status: 404,
+                message: format!("Unknown endpoint {path}"),
+            }),
+        };
+
+        match result {
+            Ok(response) => response,
+            Err(error) => (error.status, json!({ "error": error.message })),
+        }
+    }
+
+    fn stats(&self) -> Value {
+        let index = self.index.current();
+        let reload_status = self.reload_status.lock().unwrap();
+        json!({
+            "n_documents": index.len(),
+            "dim": index.dim(),
+            "nnz": index.nnz(),
+            "space_usage_byte": index.space_usage_byte(),
+            "configuration": index.config(),
+            "index_version": self.index.version(),
+            "reloading": reload_status.running,
+            "last_reload_error": reload_status.last_error,
+            "vocabulary_size": self.vocabulary.as_ref().map(|vocabulary| vocabulary.len()),
+            "uptime_secs": self.start.elapsed().as_secs(),
+            "n_requests": self.n_requests.load(Ordering::Relaxed),
+            "n_queries": self.n_queries.load(Ordering::Relaxed),
+        })
+    }
+
+    fn search(&self, body: &str) -> Result<Value, RequestError> {
+        let request: SearchRequest = serde_json::from_str(body)
+            .map_err(|e| RequestError::bad_request(format!("Invalid request: {e}")))?;
+
+        // The whole request is served by the same index, even if it is reloaded meanwhile.
+        let index = self.index.current();
+        let (k, config) = self.search_configuration(request.params)?;
+        let (components, values) = self.parse_query(request.query, index.dim())?;
+
+        self.n_queries.fetch_add(1, Ordering::Relaxed);
+        let results = index.search_with_config(&components, &values, k, &config);
+
+        Ok(json!({ "results": results_to_json(&results) }))
+    }
+
+    /// Searches the queries of the batch in parallel.
+    fn batch_search(&self, body: &str) -> Result<Value, RequestError> {
+        let request: BatchSearchRequest = serde_json::from_str(body)
+            .map_err(|e| RequestError::bad_request(format!("Invalid request: {e}")))?;
+
+        let index = self.index.current();
+        let (k, config) = self.search_configuration(request.params)?;
+        let queries = request
+            .queries
+            .into_iter()
+            .enumerate()
+            .map(|(i, query)| {
+                self.parse_query(query, index.dim())
+                    .map_err(|e| RequestError {
+                        message: format!("Query {i}: {}", e.message),
+                        ..e
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.n_queries.fetch_add(queries.len(), Ordering::Relaxed);
+        let results: Vec<_> = queries
+            .par_iter()
+            .map(|(components, values)| {
+                results_to_json(&index.search_with_config(components, values, k, &config))
+            })
+            .collect();
+
+        Ok(json!({ "results": results }))
+    }
+
+    /// Starts replacing the index with the one in the given file of the reload directory.
+    /// The new index is loaded in a background thread, while the workers keep serving
+    /// requests with the current one. Its outcome is reported by `/stats`.
+    fn reload(&self, body: &str) -> Result<Value, RequestError> {
+        let reload_dir = self.reload_dir.as_ref().ok_or_else(|| {
+            RequestError::forbidden("Reloading is disabled, start the server with --reload-dir")
+        })?;
+        let request: ReloadRequest = serde_json::from_str(body)
+            .map_err(|e| RequestError::bad_request(format!("Invalid request: {e}")))?;
+
+        // Canonicalizing resolves `..` and symbolic links, which could lead out of the directory.
+        let path = reload_dir
+            .join(&request.index_file)
+            .canonicalize()
+            .map_err(|e| RequestError::bad_request(format!("Cannot reload the index: {e}")))?;
+        if !path.starts_with(reload_dir) {
+            return Err(RequestError::forbidden(format!(
+                "{} is not in the reload directory",
+                request.index_file
+            )));
+        }
+
+        let mut status = self.reload_status.lock().unwrap();
+        if status.running {
+            return Err(RequestError {
+                status: 409,
+                message: "Another reload is running".to_string(),
+            });
+        }
+        status.running = true;
+        status.last_error = None;
+
+        let index = Arc::clone(&self.index);
+        let reload_status = Arc::clone(&self.reload_status);
+        thread::spawn(move || {
+            let result = index.reload(&path);
+            let mut status = reload_status.lock().unwrap();
+            status.running = false;
+            if let Err(e) = result {
+                eprintln!("Cannot reload the index from {}: {e}", path.display());
+                status.last_error = Some(e.to_string());
+            }
+        });
+
+        Ok(json!({ "status": "reloading", "index_version": self.index.version() }))
+    }
+
+    fn search_configuration(
+        &self,
+        params: SearchParams,
+    ) -> Result<(usize, SearchConfiguration), RequestError> {
+        let k = params.k.unwrap_or(self.k);
+        let query_cut = params.query_cut.unwrap_or(self.query_cut);
+        let heap_factor = params.heap_factor.unwrap_or(self.heap_factor);
+
+        if k == 0 {
+            return Err(RequestError::bad_request("k must be positive"));
+        }
+
+        Ok((
+            k,
+            SearchConfiguration::default()
+                .query_cut(query_cut)
+                .heap_factor(heap_factor),
+        ))
+    }
+
+    /// Converts a query to components and values, sorted by component as the index expects.
+    /// The values of a repeated component are summed. Tokens missing from the vocabulary and
+    /// components not in the index are dropped, as they cannot match any document.
+    fn parse_query(&self, query: Query, dim: usize) -> Result<(Vec<u16>, Vec<f32>), RequestError> {
+        let mut pairs: Vec<(u16, f32)> = match query {
+            Query {
+                components: Some(components),
+                values: Some(values),
+                vector: None,
+            } => {
+                if components.len() != values.len() {
+                    return Err(RequestError::bad_request(format!(
+                        "The query has {} components but {} values",
+                        components.len(),
+                        values.len()
+                    )));
+                }
+                components.into_iter().zip(values).collect()
+            }
+            Query {
+                components: None,
+                values: None,
+                vector: Some(vector),
+            } => {
+                let vocabulary = self.vocabulary.as_ref().ok_or_else(|| {
+                    RequestError::bad_request(
+                        "The server has no vocabulary to search queries given as a vector",
+                    )
+                })?;
+                vector
+                    .iter()
+                    .filter_map(|(token, &value)| {
+                        vocabulary.get(token).map(|&component| (component, value))
+                    })
+                    .collect()
+            }
+            _ => {
+                return Err(RequestError::bad_request(
+                    "A query must have either `components` and `values`, or a `vector`",
+                ))
+            }
+        };
+
+        pairs.retain(|&(component, _)| (component as usize) < dim);
+        pairs.sort_unstable_by_key(|&(component, _)| component);
+
+        let mut components = Vec::with_capacity(pairs.len());
+        let mut values: Vec<f32> = Vec::with_capacity(pairs.len());
+        for (component, value) in pairs {
+            if components.last() == Some(&component) {
+                *values.last_mut().unwrap() += value;
+            } else {
+                components.push(component);
+                values.push(value);
+            }
+        }
+
+        Ok((components, values))
+    }
+}
+
+fn results_to_json(results: &[(f32, usize)]) -> Value {
+    results
+        .iter()
+        .map(|&(score, doc_id)| json!({ "doc_id": doc_id, "score": score }))
+        .collect()
+}
+
+/// Reads the body of `request`, unless it is longer than `max_body_byte`.
+fn read_body(request: &mut Request, max_body_byte: usize) -> Result<String, RequestError> {
+    let too_large = || RequestError {
+        status: 413,
+        message: format!("The body is larger than {max_body_byte} bytes"),
+    };
+    if request
+        .body_length()
+        .is_some_and(|length| length > max_body_byte)
+    {
+        return Err(too_large());
+    }
+
+    // The declared length may be missing, e.g., with a chunked body, so the reader is bounded too.
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(max_body_byte as u64 + 1)
+        .read_to_string(&mut body)
+        .map_err(|e| RequestError::bad_request(format!("Invalid body: {e}")))?;
+    if body.len() > max_body_byte {
+        return Err(too_large());
+    }
+
+    Ok(body)
+}
+
+/// Serves the requests received by `server` with `n_workers` threads, rejecting bodies longer
+/// than `max_body_byte`. Returns when the server is unblocked.
+fn serve(server: Arc<Server>, service: Arc<SearchService>, n_workers: usize, max_body_byte: usize) {
+    let workers: Vec<_> = (0..n_workers)
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let service = Arc::clone(&service);
+            thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    let (status, value) = match read_body(&mut request, max_body_byte) {
+                        Ok(body) => service.handle(request.method(), request.url(), &body),
+                        Err(error) => (error.status, json!({ "error": error.message })),
+                    };
+
+                    let response = Response::from_string(value.to_string())
+                        .with_status_code(status)
+                        .with_header(
+                            Header::from_bytes("Content-Type", "application/json").unwrap(),
+                        );
+                    if let Err(e) = request.respond(response) {
+                        eprintln!("Failed to send a response: {e}");
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+}
+
+pub fn main() {
+    let args = Args::parse();
+
+    // The index is validated as when it is reloaded.
+    let index = IndexHandle::load(&args.index_file).unwrap_or_else(|e| {
+        eprintln!("Cannot load the index: {e}");
+        process::exit(1);
+    });
+
+    let vocabulary = args.vocabulary.map(|path| {
+        let reader = BufReader::new(File::open(path).unwrap());
+        serde_json::from_reader::<_, HashMap<String, u16>>(reader).unwrap()
+    });
+
+    println!("Number of documents: {}", index.current().len());
+
+    let reload_dir = args
+        .reload_dir
+        .map(|path| fs::canonicalize(path).expect("The reload directory does not exist"));
+
+    let service = SearchService::new(
+        index,
+        vocabulary,
+        reload_dir,
+        args.k,
+        args.query_cut,
+        args.heap_factor,
+    );
+
+    let n_workers = args.n_workers.unwrap_or_else(|| {
+        thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+
+    let server = Server::http(&args.address).unwrap();
+    println!("Listening on {} with {n_workers} workers", args.address);
+
+    serve(
+        Arc::new(server),
+        Arc::new(service),
+        n_workers,
+        args.max_body_mib << 20,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use seismic::inverted_index::{BlockingStrategy, Configuration, PruningStrategy};
+    use seismic::{InvertedIndex, SparseDataset};
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::path::Path;
+
+    fn service() -> SearchService {
+        let dataset: SparseDataset<f32> = (0..100_u16)
+            .map(|i| (vec![i % 10, 10 + i % 7], vec![1.0 + i as f32, 1.0]))
+            .collect();
+        let config = Configuration::default()
+            .pruning_strategy(PruningStrategy::FixedSize { n_postings: 1000 })
+            .blocking_strategy(BlockingStrategy::FixedSize { block_size: 10 });
+        let index = InvertedIndex::build(dataset.quantize_f16(), config);
+        let vocabulary = HashMap::from([
+            ("a".to_string(), 3),
+            ("b".to_string(), 12),
+            ("c".to_string(), 5),
+        ]);
+
+        SearchService::new(IndexHandle::new(index), Some(vocabulary), None, 10, 10, 0.7)
+    }
+
+    #[test]
+    fn test_handle_requests() {
+        let service = service();
+
+        let (status, value) = service.handle(&Method::Get, "/health", "");
+        assert_eq!(status, 200);
+        assert_eq!(value["status"], "ok");
+
+        // Documents 93, 83, ... are the best ones for component 3.
+        let (status, value) = service.handle(
+            &Method::Post,
+            "/search",
+            r#"{"components": [3], "values": [1.0], "k": 2}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(value["results"][0]["doc_id"], 93);
+        assert_eq!(value["results"][1]["doc_id"], 83);
+
+        // The same query with a token, and an unknown token which is ignored.
+        let (status, by_token) = service.handle(
+            &Method::Post,
+            "/search",
+            r#"{"vector": {"a": 1.0, "unknown": 5.0}, "k": 2}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(by_token, value);
+
+        let (status, value) = service.handle(
+            &Method::Post,
+            "/batch_search",
+            r#"{"queries": [{"components": [3], "values": [1.0]}, {"vector": {"b": 1.0}}], "k": 3}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(value["results"][0].as_array().unwrap().len(), 3);
+        assert_eq!(value["results"][1].as_array().unwrap().len(), 3);
+
+        let (status, _) = service.handle(
+            &Method::Post,
+            "/search",
+            r#"{"components": [3, 4], "values": [1.0]}"#,
+        );
+        assert_eq!(status, 400);
+        let (status, _) = service.handle(&Method::Get, "/search", "");
+        assert_eq!(status, 405);
+        let (status, _) = service.handle(&Method::Get, "/unknown", "");
+        assert_eq!(status, 404);
+
+        let (_, stats) = service.handle(&Method::Get, "/stats", "");
+        assert_eq!(stats["n_documents"], 100);
+        assert_eq!(stats["n_queries"], 4);
+        assert_eq!(stats["n_requests"], 8);
+    }
+
+    #[test]
+    fn test_search_unsorted_queries() {
+        let service = service();
+        let index = service.index.current();
+        let expected = json!({
+            "results": results_to_json(&index.search(&[3, 5, 12], &[1.0, 0.5, 2.0], 10, 10, 0.7))
+        });
+
+        // The components of a vector follow the order of the tokens in the map.
+        let (status, value) = service.handle(
+            &Method::Post,
+            "/search",
+            r#"{"vector": {"b": 2.0, "a": 1.0, "c": 0.5}}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(value, expected);
+
+        let (status, value) = service.handle(
+            &Method::Post,
+            "/search",
+            r#"{"components": [12, 3, 5], "values": [2.0, 1.0, 0.5]}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(value, expected);
+
+        // The values of a repeated component are summed.
+        let (status, value) = service.handle(
+            &Method::Post,
+            "/search",
+            r#"{"components": [12, 5, 3, 12], "values": [1.5, 0.5, 1.0, 0.5]}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = std::env::temp_dir().join(format!("seismic_server_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let index = InvertedIndex::build(
+            [(vec![3_u16], vec![2.0_f32])]
+                .into_iter()
+                .collect::<SparseDataset<f32>>()
+                .quantize_f16(),
+            Configuration::default(),
+        );
+        fs::write(dir.join("new.index"), bincode::serialize(&index).unwrap()).unwrap();
+        fs::write(dir.join("corrupted.index"), b"not an index").unwrap();
+
+        // Reloading is disabled without a reload directory.
+        let body = r#"{"index_file": "new.index"}"#;
+        let (status, _) = service().handle(&Method::Post, "/reload", body);
+        assert_eq!(status, 403);
+
+        let service = SearchService {
+            reload_dir: Some(dir.canonicalize().unwrap()),
+            ..service()
+        };
+        let wait_reload = || loop {
+            let (_, stats) = service.handle(&Method::Get, "/stats", "");
+            if stats["reloading"] == false {
+                return stats;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        };
+
+        let (status, _) = service.handle(
+            &Method::Post,
+            "/reload",
+            r#"{"index_file": "nonexistent.index"}"#,
+        );
+        assert_eq!(status, 400);
+        let outside = dir.with_extension("index");
+        fs::write(&outside, bincode::serialize(&index).unwrap()).unwrap();
+        let body_outside = json!({
+            "index_file": Path::new("..").join(outside.file_name().unwrap())
+        });
+        let (status, _) = service.handle(&Method::Post, "/reload", &body_outside.to_string());
+        assert_eq!(status, 403);
+
+        // A file which is not an index is reported once its load fails.
+        let (status, _) = service.handle(
+            &Method::Post,
+            "/reload",
+            r#"{"index_file": "corrupted.index"}"#,
+        );
+        assert_eq!(status, 202);
+        let stats = wait_reload();
+        assert!(stats["last_reload_error"].is_string());
+        assert_eq!(stats["index_version"], 0);
+
+        let (status, _) = service.handle(&Method::Post, "/reload", body);
+        assert_eq!(status, 202);
+        let stats = wait_reload();
+        assert!(stats["last_reload_error"].is_null());
+        assert_eq!(stats["index_version"], 1);
+
+        let (_, value) = service.handle(
+            &Method::Post,
+            "/search",
+            r#"{"components": [3], "values": [1.0]}"#,
+        );
+        assert_eq!(value["results"], json!([{ "doc_id": 0, "score": 2.0 }]));
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_file(outside).unwrap();
+    }
+
+    #[test]
+    fn test_serve_on_localhost() {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let address = server.server_addr().to_ip().unwrap();
+
+        let worker = {
+            let server = Arc::clone(&server);
+            thread::spawn(move || serve(server, Arc::new(service()), 2, 1024))
+        };
+
+        let post = |body: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(
+                stream,
+                "POST /search HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = post(r#"{"components": [3], "values": [1.0], "k": 1}"#);
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with(r#"{"results":[{"doc_id":93,"score":94.0}]}"#));
+
+        let response = post(&format!(
+            r#"{{"components": [3], "values": [1.0], "padding": "{}"}}"#,
+            " ".repeat(1024)
+        ));
+        assert!(response.starts_with("HTTP/1.1 413"));
+
+        for _ in 0..2 {
+            server.unblock();
+        }
+        worker.join().unwrap();
+    }
+}
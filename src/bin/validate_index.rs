@@ -0,0 +1,39 @@
+use std::fs;
+use std::process;
+
+use half::f16;
+use seismic::InvertedIndex;
+
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// The path of the index.
+    #[clap(short, long, value_parser)]
+    index_file: String,
+}
+
+pub fn main() {
+    let args = Args::parse();
+
+    let serialized: Vec<u8> = fs::read(&args.index_file).unwrap();
+    let inverted_index = match bincode::deserialize::<InvertedIndex<f16>>(&serialized) {
+        Ok(inverted_index) => inverted_index,
+        Err(e) => {
+            eprintln!("{} cannot be deserialized: {e}", args.index_file);
+            process::exit(1);
+        }
+    };
+
+    match inverted_index.validate() {
+        Ok(()) => println!("The index is valid"),
+        Err(violations) => {
+            for violation in violations.iter() {
+                println!("{violation}");
+            }
+            eprintln!("The index has {} violations", violations.len());
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! This module provides hooks to follow the construction of an [`InvertedIndex`](crate::InvertedIndex).
+//!
+//! The building functions never write to the standard output. Instead, they notify a
+//! [`BuildObserver`] when each phase of the construction starts and finishes, and how many
+//! items of a phase have been processed. The [`SilentObserver`] ignores the notifications,
+//! while the [`TerminalObserver`] prints them and shows a progress bar.
+//!
+//! An observer can also cancel the construction, see [`BuildObserver::is_cancelled`]. The
+//! building functions which take an observer then return a [`BuildCancelled`] error.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+
+/// A phase of the construction of an index.
+/// - `DistributingPostings`: The postings of each document are distributed to the posting lists of its components.
+/// - `PruningPostings`: The posting lists are pruned with the pruning strategy.
+/// - `CompressingForwardIndex`: The vectors are compressed, if required by the forward index strategy.
+/// - `BuildingPostingLists`: The postings of each list are divided into blocks and the blocks are summarized.
+///   Progress is reported by number of posting lists.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, Serialize, Deserialize)]
+pub enum BuildPhase {
+    DistributingPostings,
+    PruningPostings,
+    CompressingForwardIndex,
+    BuildingPostingLists,
+}
+
+impl BuildPhase {
+    /// Returns the name of the phase in snake case, e.g., `"building_posting_lists"`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DistributingPostings => "distributing_postings",
+            Self::PruningPostings => "pruning_postings",
+            Self::CompressingForwardIndex => "compressing_forward_index",
+            Self::BuildingPostingLists => "building_posting_lists",
+        }
+    }
+}
+
+impl fmt::Display for BuildPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Self::DistributingPostings => "Distributing postings",
+            Self::PruningPostings => "Pruning postings",
+            Self::CompressingForwardIndex => "Compressing forward index",
+            Self::BuildingPostingLists => "Building posting lists",
+        };
+        write!(f, "{description}")
+    }
+}
+
+/// Receives notifications on the progress of the construction of an index.
+///
+/// `progress` and `is_cancelled` may be called concurrently by the threads building the index,
+/// the other methods are called by the thread which started the construction. All the methods
+/// do nothing by default.
+pub trait BuildObserver: Sync {
+    /// Called when `phase` starts. `total` is the number of items processed by the phase, if known.
+    fn phase_started(&self, _phase: BuildPhase, _total: Option<usize>) {}
+
+    /// Called when `n` more items of `phase` have been processed.
+    fn progress(&self, _phase: BuildPhase, _n: usize) {}
+
+    /// Called when `phase` finishes, after running for `elapsed`.
+    fn phase_finished(&self, _phase: BuildPhase, _elapsed: Duration) {}
+
+    /// Called between the phases and between the posting lists built by a phase. If it returns
+    /// `true`, the construction is abandoned and returns a [`BuildCancelled`] error.
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// The error returned by a construction cancelled by its observer.
+///
+/// # Example
+/// ```
+/// use seismic::build_observer::{BuildCancelled, BuildObserver};
+/// use seismic::inverted_index::Configuration;
+/// use seismic::{InvertedIndex, SparseDataset};
+///
+/// struct Cancel;
+///
+/// impl BuildObserver for Cancel {
+///     fn is_cancelled(&self) -> bool {
+///         true
+///     }
+/// }
+///
+/// let data = vec![(vec![0, 2], vec![1.0, 2.0]), (vec![1, 3], vec![4.0, 5.0])];
+/// let dataset: SparseDataset<f32> = data.into_iter().collect();
+/// let result = InvertedIndex::build_with_observer(dataset, Configuration::default(), &Cancel);
+///
+/// assert_eq!(result.unwrap_err(), BuildCancelled);
+/// ```
+#[derive(Default, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct BuildCancelled;
+
+impl fmt::Display for BuildCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the construction of the index has been cancelled")
+    }
+}
+
+impl std::error::Error for BuildCancelled {}
+
+// Returns an error if `observer` cancels the construction.
+pub(crate) fn check_cancelled(observer: &dyn BuildObserver) -> Result<(), BuildCancelled> {
+    if observer.is_cancelled() {
+        Err(BuildCancelled)
+    } else {
+        Ok(())
+    }
+}
+
+/// An observer which ignores all the notifications.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct SilentObserver;
+
+impl BuildObserver for SilentObserver {}
+
+/// An observer which prints each phase with its duration to the standard output,
+/// and shows a progress bar for the phases with a known number of items.
+#[derive(Default, Debug)]
+pub struct TerminalObserver {
+    progress_bar: Mutex<Option<ProgressBar>>,
+}
+
+impl TerminalObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BuildObserver for TerminalObserver {
+    fn phase_started(&self, phase: BuildPhase, total: Option<usize>) {
+        match total {
+            Some(total) => {
+                println!("\t{phase} ({total})");
+                *self.progress_bar.lock().unwrap() = Some(ProgressBar::new(total as u64));
+            }
+            None => {
+                print!("\t{phase} ");
+                io::stdout().flush().unwrap();
+            }
+        }
+    }
+
+    fn progress(&self, _phase: BuildPhase, n: usize) {
+        if let Some(progress_bar) = self.progress_bar.lock().unwrap().as_ref() {
+            progress_bar.inc(n as u64);
+        }
+    }
+
+    fn phase_finished(&self, phase: BuildPhase, elapsed: Duration) {
+        match self.progress_bar.lock().unwrap().take() {
+            Some(progress_bar) => {
+                progress_bar.finish();
+                println!("\t{phase}: {} secs", elapsed.as_secs());
+            }
+            None => println!("{} secs", elapsed.as_secs()),
+        }
+    }
+}
+
+// Runs `f` as `phase`, notifying `observer` when it starts and finishes.
+// The construction is abandoned before and after the phase if `observer` cancels it.
+pub(crate) fn observe_phase<R>(
+    observer: &dyn BuildObserver,
+    phase: BuildPhase,
+    total: Option<usize>,
+    f: impl FnOnce() -> Result<R, BuildCancelled>,
+) -> Result<R, BuildCancelled> {
+    check_cancelled(observer)?;
+    observer.phase_started(phase, total);
+    let time = Instant::now();
+    let result = f()?;
+    observer.phase_finished(phase, time.elapsed());
+    check_cancelled(observer)?;
+
+    Ok(result)
+}
@@ -0,0 +1,591 @@
+//! This module provides a compressed, read-only representation of a sparse dataset.
+//!
+//! Every vector is encoded as a self-contained record of bytes. The components are
+//! delta-encoded and bit-packed with the smallest bit width able to represent the
+//! largest gap of the vector. The values are scalar quantized with either 8 or 4 bits
+//! per value, using a per-vector minimum and step.
+//!
+//! The layout of a record with `n` components is the following:
+//!  - `n` in 2 bytes;
+//!  - The bit width `b` of the gaps in 1 byte;
+//!  - The minimum and the step of the quantization as two `f32`;
+//!  - The first component in 2 bytes, followed by `n-1` gaps of `b` bits each;
+//!  - The `n` quantization codes, either one byte each or two per byte.
+//!
+//! Records are decoded on the fly while computing dot products, so the dataset can replace
+//! a [`SparseDataset`] as forward index of an [`InvertedIndex`](crate::InvertedIndex).
+
+use std::mem;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::prefetch_read_NTA;
+use crate::validation::{check_components, Violation};
+use crate::{DataType, SpaceUsage, SparseDataset};
+
+const HEADER_SIZE: usize = 2 + 1 + 4 + 4;
+
+/// The number of bits used to quantize each value of a [`CompressedSparseDataset`].
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ValueQuantization {
+    Bits8,
+    Bits4,
+}
+
+impl ValueQuantization {
+    #[inline]
+    fn n_levels(&self) -> usize {
+        match self {
+            Self::Bits8 => 255,
+            Self::Bits4 => 15,
+        }
+    }
+
+    #[inline]
+    fn codes_size_byte(&self, n: usize) -> usize {
+        match self {
+            Self::Bits8 => n,
+            Self::Bits4 => n.div_ceil(2),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct CompressedSparseDataset {
+    n_vecs: usize,
+    d: usize,
+    nnz: usize,
+    quantization: ValueQuantization,
+    offsets: Box<[usize]>, // byte offset of the record of each vector
+    data: Box<[u8]>,
+}
+
+impl CompressedSparseDataset {
+    /// Compresses `dataset` quantizing its values with the given `quantization`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a vector has more than `u16::MAX` components.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use seismic::SparseDataset;
+    /// use seismic::compressed_dataset::{CompressedSparseDataset, ValueQuantization};
+    ///
+    /// let data = vec![
+    ///                 (vec![0, 2, 4],    vec![1.0, 2.0, 3.0]),
+    ///                 (vec![1, 3],       vec![4.0, 5.0]),
+    ///                 (vec![0, 1, 2, 3], vec![1.0, 2.0, 3.0, 4.0])
+    ///                 ];
+    ///
+    /// let dataset: SparseDataset<f32> = data.into_iter().collect();
+    /// let compressed = CompressedSparseDataset::from_dataset(&dataset, ValueQuantization::Bits8);
+    ///
+    /// let (components, values) = compressed.get(1);
+    /// assert_eq!(components, vec![1, 3]);
+    /// assert_eq!(values, vec![4.0, 5.0]);
+    /// ```
+    pub fn from_dataset<T>(dataset: &SparseDataset<T>, quantization: ValueQuantization) -> Self
+    where
+        T: DataType,
+    {
+        let mut offsets = Vec::with_capacity(dataset.len() + 1);
+        let mut data = Vec::with_capacity(dataset.nnz() * 2);
+
+        offsets.push(0);
+        for (components, values) in dataset.iter() {
+            Self::encode_vector(components, values, quantization, &mut data);
+            offsets.push(data.len());
+        }
+
+        Self {
+            n_vecs: dataset.len(),
+            d: dataset.dim(),
+            nnz: dataset.nnz(),
+            quantization,
+            offsets: offsets.into_boxed_slice(),
+            data: data.into_boxed_slice(),
+        }
+    }
+
+    /// Appends the vectors of `other` after those of the dataset.
+    ///
+    /// # Panics
+    /// Panics if the two datasets quantize values with a different number of bits.
+    pub fn append(&mut self, other: &Self) {
+        assert_eq!(
+            self.quantization, other.quantization,
+            "Datasets with different quantizations cannot be appended"
+        );
+
+        let data_len = self.data.len();
+        let mut offsets = self.offsets[..self.n_vecs].to_vec();
+        offsets.extend(other.offsets.iter().map(|&offset| offset + data_len));
+        let mut data = mem::take(&mut self.data).into_vec();
+        data.extend_from_slice(&other.data);
+
+        self.n_vecs += other.n_vecs;
+        self.d = self.d.max(other.d);
+        self.nnz += other.nnz;
+        self.offsets = offsets.into_boxed_slice();
+        self.data = data.into_boxed_slice();
+    }
+
+    fn encode_vector<T>(
+        components: &[u16],
+        values: &[T],
+        quantization: ValueQuantization,
+        data: &mut Vec<u8>,
+    ) where
+        T: DataType,
+    {
+        assert!(
+            components.len() <= u16::MAX as usize,
+            "Vectors cannot have more than {} components",
+            u16::MAX
+        );
+
+        let max_gap = components
+            .windows(2)
+            .map(|w| w[1] - w[0])
+            .max()
+            .unwrap_or(0);
+        let width = u16::BITS - max_gap.leading_zeros();
+
+        let values: Vec<f32> = values.iter().map(|v| v.to_f32().unwrap()).collect();
+        let (min, max) = values
+            .iter()
+            .fold((f32::MAX, f32::MIN), |acc, &v| (acc.0.min(v), acc.1.max(v)));
+        let (min, step) = if values.is_empty() {
+            (0.0, 0.0)
+        } else {
+            (min, (max - min) / quantization.n_levels() as f32)
+        };
+
+        data.extend((components.len() as u16).to_le_bytes());
+        data.push(width as u8);
+        data.extend(min.to_le_bytes());
+        data.extend(step.to_le_bytes());
+
+        if let Some((&first, _)) = components.split_first() {
+            data.extend(first.to_le_bytes());
+
+            let mut writer = BitWriter::new(data);
+            for w in components.windows(2) {
+                writer.write((w[1] - w[0]) as u64, width);
+            }
+            writer.finish();
+        }
+
+        let codes = values.iter().map(|&v| {
+            if step == 0.0 {
+                0
+            } else {
+                (((v - min) / step).round() as usize).min(quantization.n_levels()) as u8
+            }
+        });
+
+        match quantization {
+            ValueQuantization::Bits8 => data.extend(codes),
+            ValueQuantization::Bits4 => {
+                for pair in codes.collect::<Vec<_>>().chunks(2) {
+                    data.push(pair[0] | (pair.get(1).copied().unwrap_or(0) << 4));
+                }
+            }
+        }
+    }
+
+    /// Decodes all the vectors into a [`SparseDataset`] with their dequantized values.
+    #[must_use]
+    pub fn decompress(&self) -> SparseDataset<f32> {
+        (0..self.n_vecs).map(|id| self.get(id)).collect()
+    }
+
+    /// Decodes the components and the (dequantized) values of the vector with the specified `id`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the specified `id` is out of range.
+    #[must_use]
+    pub fn get(&self, id: usize) -> (Vec<u16>, Vec<f32>) {
+        assert!(id < self.n_vecs, "The id {id} is out of range");
+
+        self.get_with_offset(self.offsets[id])
+    }
+
+    /// Decodes the components and the (dequantized) values of the vector whose record
+    /// starts at the specified `offset`.
+    #[must_use]
+    pub fn get_with_offset(&self, offset: usize) -> (Vec<u16>, Vec<f32>) {
+        let record = Record::new(&self.data[offset..], self.quantization);
+
+        let mut components = Vec::with_capacity(record.n);
+        let mut values = Vec::with_capacity(record.n);
+        record.for_each(|c, code| {
+            components.push(c);
+            values.push(record.min + code as f32 * record.step);
+        });
+
+        (components, values)
+    }
+
+    /// Computes the dot product between the dense `query` and the vector whose record starts at
+    /// the specified `offset`. The vector is decoded on the fly.
+    #[inline]
+    #[must_use]
+    pub fn dot_product_dense_with_offset(&self, query: &[f32], offset: usize) -> f32 {
+        let record = Record::new(&self.data[offset..], self.quantization);
+
+        // Dequantization is factored out: sum_i q_i * (min + c_i * step)
+        let mut sum_query = 0.0;
+        let mut sum_codes = 0.0;
+        record.for_each(|c, code| {
+            let q = query[c as usize];
+            sum_query += q;
+            sum_codes += q * code as f32;
+        });
+
+        record.min * sum_query + record.step * sum_codes
+    }
+
+    /// Prefetches the record of the vector with the specified offset and length into the CPU cache.
+    #[inline]
+    pub fn prefetch_vec_with_offset(&self, offset: usize, len: usize) {
+        // Gaps take at most two bytes, codes at most one.
+        let end = (offset + HEADER_SIZE + 3 * len).min(self.data.len());
+
+        for i in (offset..end).step_by(64) {
+            prefetch_read_NTA(&self.data, i);
+        }
+    }
+
+    /// Returns the offset of the record of the vector with the specified index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `id` is out of range.
+    #[must_use]
+    #[inline]
+    pub fn vector_offset(&self, id: usize) -> usize {
+        assert!(id < self.n_vecs, "The id is out of range");
+
+        self.offsets[id]
+    }
+
+    /// Returns the number of non-zero components of the vector with the specified index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `id` is out of range.
+    #[must_use]
+    #[inline]
+    pub fn vector_len(&self, id: usize) -> usize {
+        assert!(id < self.n_vecs, "The id is out of range");
+
+        u16::from_le_bytes([self.data[self.offsets[id]], self.data[self.offsets[id] + 1]]) as usize
+    }
+
+    /// Converts the `offset` of a record to the id of its vector.
+    ///
+    /// # Panics
+    /// Panics if the `offset` is not the first position of a record.
+    #[must_use]
+    #[inline]
+    pub fn offset_to_id(&self, offset: usize) -> usize {
+        self.offsets.binary_search(&offset).unwrap()
+    }
+
+    /// Returns the quantization used for the values.
+    #[must_use]
+    pub fn quantization(&self) -> ValueQuantization {
+        self.quantization
+    }
+
+    /// Returns the number of vectors in the dataset.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.n_vecs
+    }
+
+    /// Checks if the dataset is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.n_vecs == 0
+    }
+
+    /// Returns the number of components of the dataset, i.e., one plus the ID of the largest component.
+    #[must_use]
+    pub fn dim(&self) -> usize {
+        self.d
+    }
+
+    /// Returns the number of non-zero components in the dataset.
+    #[must_use]
+    pub fn nnz(&self) -> usize {
+        self.nnz
+    }
+
+    // Appends to `violations` the vectors with invalid offsets or records, unsorted components,
+    // or components not smaller than `dim`.
+    pub(crate) fn check_vectors(&self, dim: usize, violations: &mut Vec<Violation>) {
+        if self.offsets.len() != self.n_vecs + 1 {
+            violations.push(Violation::OffsetsLength {
+                n_vectors: self.n_vecs,
+                n_offsets: self.offsets.len(),
+            });
+            return;
+        }
+
+        for (id, range) in self.offsets.windows(2).enumerate() {
+            let (start, end) = (range[0], range[1]);
+            if start > end || end > self.data.len() {
+                violations.push(Violation::VectorRange {
+                    id,
+                    start,
+                    end,
+                    size: self.data.len(),
+                });
+                continue;
+            }
+
+            let data = &self.data[start..end];
+            let expected = Record::size(data, self.quantization);
+            if expected != Some(data.len()) {
+                violations.push(Violation::RecordSize {
+                    id,
+                    size: data.len(),
+                    expected: expected.unwrap_or(HEADER_SIZE),
+                });
+                continue;
+            }
+
+            let mut components = Vec::new();
+            Record::new(data, self.quantization).for_each(|c, _| components.push(c));
+            check_components(id, &components, dim, violations);
+        }
+    }
+
+    // Same as `offset_to_id`, but returns `None` if `offset` is not the first position of a record.
+    #[inline]
+    pub(crate) fn checked_offset_to_id(&self, offset: usize) -> Option<usize> {
+        let offsets = self.offsets.get(..self.n_vecs)?;
+        offsets.binary_search(&offset).ok()
+    }
+}
+
+impl SpaceUsage for CompressedSparseDataset {
+    /// Returns the size of the dataset in bytes.
+    fn space_usage_byte(&self) -> usize {
+        self.n_vecs.space_usage_byte()
+            + self.d.space_usage_byte()
+            + self.nnz.space_usage_byte()
+            + std::mem::size_of::<ValueQuantization>()
+            + self.offsets.space_usage_byte()
+            + self.data.space_usage_byte()
+    }
+}
+
+// A view over the record of a single vector.
+struct Record<'a> {
+    n: usize,
+    width: u32,
+    min: f32,
+    step: f32,
+    gaps: &'a [u8],
+    codes: &'a [u8],
+    quantization: ValueQuantization,
+}
+
+impl<'a> Record<'a> {
+    #[inline]
+    fn new(data: &'a [u8], quantization: ValueQuantization) -> Self {
+        let n = u16::from_le_bytes([data[0], data[1]]) as usize;
+        let width = data[2] as u32;
+        let min = f32::from_le_bytes(data[3..7].try_into().unwrap());
+        let step = f32::from_le_bytes(data[7..11].try_into().unwrap());
+
+        let gaps_size = Self::gaps_size(n, width);
+        let gaps = &data[HEADER_SIZE..HEADER_SIZE + gaps_size];
+        let codes = &data
+            [HEADER_SIZE + gaps_size..HEADER_SIZE + gaps_size + quantization.codes_size_byte(n)];
+
+        Self {
+            n,
+            width,
+            min,
+            step,
+            gaps,
+            codes,
+            quantization,
+        }
+    }
+
+    #[inline]
+    fn gaps_size(n: usize, width: u32) -> usize {
+        if n == 0 {
+            0
+        } else {
+            2 + ((n - 1) * width as usize).div_ceil(8)
+        }
+    }
+
+    // Returns the size in bytes of the record starting at `data` according to its header,
+    // or `None` if the header is truncated or invalid.
+    fn size(data: &[u8], quantization: ValueQuantization) -> Option<usize> {
+        if data.len() < HEADER_SIZE || data[2] as u32 > u16::BITS {
+            return None;
+        }
+
+        let n = u16::from_le_bytes([data[0], data[1]]) as usize;
+        Some(HEADER_SIZE + Self::gaps_size(n, data[2] as u32) + quantization.codes_size_byte(n))
+    }
+
+    // Calls `f` with every pair (component, quantization code) of the record.
+    #[inline]
+    fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(u16, u8),
+    {
+        if self.n == 0 {
+            return;
+        }
+
+        let mut component = u16::from_le_bytes([self.gaps[0], self.gaps[1]]);
+        let mut reader = BitReader::new(&self.gaps[2..]);
+
+        for i in 0..self.n {
+            if i > 0 {
+                component = component.wrapping_add(reader.read(self.width) as u16);
+            }
+
+            let code = match self.quantization {
+                ValueQuantization::Bits8 => self.codes[i],
+                ValueQuantization::Bits4 => (self.codes[i >> 1] >> ((i & 1) * 4)) & 0xF,
+            };
+
+            f(component, code);
+        }
+    }
+}
+
+// Appends values of at most 32 bits to a vector of bytes, least significant bits first.
+struct BitWriter<'a> {
+    data: &'a mut Vec<u8>,
+    buffer: u64,
+    n_bits: u32,
+}
+
+impl<'a> BitWriter<'a> {
+    fn new(data: &'a mut Vec<u8>) -> Self {
+        Self {
+            data,
+            buffer: 0,
+            n_bits: 0,
+        }
+    }
+
+    #[inline]
+    fn write(&mut self, value: u64, width: u32) {
+        self.buffer |= value << self.n_bits;
+        self.n_bits += width;
+
+        while self.n_bits >= 8 {
+            self.data.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.n_bits -= 8;
+        }
+    }
+
+    fn finish(self) {
+        if self.n_bits > 0 {
+            self.data.push(self.buffer as u8);
+        }
+    }
+}
+
+// Reads values written by a `BitWriter`.
+struct BitReader<'a> {
+    data: &'a [u8],
+    buffer: u64,
+    n_bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    #[inline]
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            buffer: 0,
+            n_bits: 0,
+        }
+    }
+
+    #[inline]
+    fn read(&mut self, width: u32) -> u64 {
+        while self.n_bits < width {
+            let (&byte, rest) = self.data.split_first().unwrap();
+            self.data = rest;
+            self.buffer |= (byte as u64) << self.n_bits;
+            self.n_bits += 8;
+        }
+
+        let value = self.buffer & ((1 << width) - 1);
+        self.buffer >>= width;
+        self.n_bits -= width;
+
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Checks that components are exact and values are within the quantization error.
+    #[test]
+    fn test_roundtrip_and_dot_product() {
+        let data = vec![
+            (
+                vec![0_u16, 2, 4, 1000, 30000],
+                vec![0.5_f32, 2.0, 3.0, 1.25, 7.0],
+            ),
+            (vec![7], vec![4.0]),
+            (
+                vec![1, 2, 3, 4, 5, 6, 7],
+                vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0],
+            ),
+        ];
+        let dataset: SparseDataset<f32> = data.clone().into_iter().collect();
+        let query: Vec<f32> = (0..dataset.dim()).map(|i| (i % 5) as f32).collect();
+
+        for quantization in [ValueQuantization::Bits8, ValueQuantization::Bits4] {
+            let compressed = CompressedSparseDataset::from_dataset(&dataset, quantization);
+            let max_error = 7.0 / quantization.n_levels() as f32;
+
+            assert_eq!(compressed.len(), dataset.len());
+            assert_eq!(compressed.nnz(), dataset.nnz());
+
+            for (id, (components, values)) in data.iter().enumerate() {
+                assert_eq!(compressed.vector_len(id), components.len());
+
+                let (c_components, c_values) = compressed.get(id);
+                assert_eq!(&c_components, components);
+                for (v, c_v) in values.iter().zip(c_values) {
+                    assert!((v - c_v).abs() <= max_error);
+                }
+
+                let offset = compressed.vector_offset(id);
+                assert_eq!(compressed.offset_to_id(offset), id);
+
+                let expected: f32 = components
+                    .iter()
+                    .zip(values)
+                    .map(|(&c, &v)| query[c as usize] * v)
+                    .sum();
+                let dot = compressed.dot_product_dense_with_offset(&query, offset);
+                assert!((expected - dot).abs() <= max_error * query.iter().sum::<f32>());
+            }
+        }
+    }
+}
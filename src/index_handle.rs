@@ -0,0 +1,229 @@
+//! This module provides a handle to an index which can be replaced while it is being searched.
+//!
+//! The handle stores the current index behind an `Arc`. Every search clones the `Arc` and
+//! runs on that snapshot, so replacing the index only redirects the searches started
+//! afterwards, while the in-flight ones finish on the old index. The old index is dropped
+//! when its last search completes.
+
+use std::fs;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+
+use serde::de::DeserializeOwned;
+
+use crate::inverted_index::SearchConfiguration;
+use crate::{DataType, InvertedIndex};
+
+/// A shared handle to an [`InvertedIndex`] which can be atomically swapped with a new one.
+///
+/// # Example
+/// ```
+/// use seismic::inverted_index::{Configuration, PruningStrategy, SummarizationStrategy};
+/// use seismic::{IndexHandle, InvertedIndex, SparseDataset};
+/// use std::sync::Arc;
+///
+/// let config = Configuration::default()
+///     .pruning_strategy(PruningStrategy::FixedSize { n_postings: 10 })
+///     .summarization_strategy(SummarizationStrategy::EnergyPerserving { summary_energy: 1.0 });
+///
+/// let data = vec![(vec![0, 2], vec![1.0, 2.0]), (vec![1, 3], vec![4.0, 5.0])];
+/// let dataset: SparseDataset<f32> = data.into_iter().collect();
+/// let handle = IndexHandle::new(InvertedIndex::build(dataset, config.clone()));
+///
+/// // A search holding the current index is not affected by a swap.
+/// let old_index = handle.current();
+///
+/// let data = vec![(vec![1, 3], vec![1.0, 1.0]), (vec![0, 2], vec![3.0, 1.0])];
+/// let dataset: SparseDataset<f32> = data.into_iter().collect();
+/// handle.swap(Arc::new(InvertedIndex::build(dataset, config)));
+///
+/// assert_eq!(handle.version(), 1);
+/// assert_eq!(old_index.search(&[1], &[1.0], 1, 1, 0.0), vec![(4.0, 1)]);
+/// assert_eq!(handle.current().search(&[1], &[1.0], 1, 1, 0.0), vec![(1.0, 0)]);
+/// ```
+pub struct IndexHandle<T>
+where
+    T: DataType,
+{
+    current: RwLock<Arc<InvertedIndex<T>>>,
+    version: AtomicUsize,
+}
+
+impl<T> IndexHandle<T>
+where
+    T: PartialOrd + DataType,
+{
+    /// Creates a handle to `index`.
+    pub fn new(index: InvertedIndex<T>) -> Self {
+        Self::from_arc(Arc::new(index))
+    }
+
+    /// Creates a handle to an index which may be shared with other owners.
+    pub fn from_arc(index: Arc<InvertedIndex<T>>) -> Self {
+        Self {
+            current: RwLock::new(index),
+            version: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the current index. The returned index stays valid even if the index of
+    /// the handle is swapped in the meantime.
+    pub fn current(&self) -> Arc<InvertedIndex<T>> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    /// Replaces the current index with `index` and returns the previous one.
+    pub fn swap(&self, index: Arc<InvertedIndex<T>>) -> Arc<InvertedIndex<T>> {
+        let mut current = self.current.write().unwrap();
+        let old_index = std::mem::replace(&mut *current, index);
+        self.version.fetch_add(1, Ordering::AcqRel);
+        old_index
+    }
+
+    /// Returns the number of times the index has been swapped.
+    pub fn version(&self) -> usize {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Searches the top-`k` results of the query on the current index.
+    /// See [`InvertedIndex::search_with_config`].
+    #[must_use]
+    pub fn search_with_config(
+        &self,
+        query_components: &[u16],
+        query_values: &[f32],
+        k: usize,
+        config: &SearchConfiguration,
+    ) -> Vec<(f32, usize)> {
+        self.current()
+            .search_with_config(query_components, query_values, k, config)
+    }
+}
+
+impl<T> IndexHandle<T>
+where
+    T: PartialOrd + DataType + DeserializeOwned,
+{
+    /// Creates a handle to the index serialized in the file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> IoResult<Self> {
+        Ok(Self::new(read_index(path.as_ref())?))
+    }
+
+    /// Loads the index serialized in the file at `path` and swaps it with the current one.
+    /// The current index is kept if the file cannot be read or does not contain a valid index,
+    /// see [`InvertedIndex::validate`].
+    pub fn reload(&self, path: impl AsRef<Path>) -> IoResult<()> {
+        let index = read_index(path.as_ref())?;
+        self.swap(Arc::new(index));
+        Ok(())
+    }
+}
+
+impl<T> IndexHandle<T>
+where
+    T: PartialOrd + DataType + DeserializeOwned + 'static,
+{
+    /// Same as [`IndexHandle::reload`], but loads the new index in a background thread.
+    /// Searches keep running on the current index until the new one is ready.
+    pub fn reload_in_background(
+        self: &Arc<Self>,
+        path: impl Into<PathBuf>,
+    ) -> JoinHandle<IoResult<()>> {
+        let handle = Arc::clone(self);
+        let path = path.into();
+        thread::spawn(move || handle.reload(path))
+    }
+}
+
+fn read_index<T>(path: &Path) -> IoResult<InvertedIndex<T>>
+where
+    T: PartialOrd + DataType + DeserializeOwned,
+{
+    let invalid_data = |reason: String| {
+        IoError::new(
+            ErrorKind::InvalidData,
+            format!("{} is not a valid index: {reason}", path.display()),
+        )
+    };
+
+    let serialized = fs::read(path)?;
+    let index: InvertedIndex<T> =
+        bincode::deserialize(&serialized).map_err(|e| invalid_data(e.to_string()))?;
+
+    // A corrupted index could panic or return wrong results while being searched.
+    index.validate().map_err(|violations| {
+        invalid_data(format!(
+            "{} ({} violations)",
+            violations[0],
+            violations.len()
+        ))
+    })?;
+
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverted_index::{Configuration, PruningStrategy, SummarizationStrategy};
+    use crate::SparseDataset;
+
+    fn index(data: Vec<(Vec<u16>, Vec<f32>)>) -> InvertedIndex<f32> {
+        let dataset: SparseDataset<f32> = data.into_iter().collect();
+        let config = Configuration::default()
+            .pruning_strategy(PruningStrategy::FixedSize { n_postings: 10 })
+            .summarization_strategy(SummarizationStrategy::EnergyPerserving {
+                summary_energy: 1.0,
+            });
+        InvertedIndex::build(dataset, config)
+    }
+
+    // A failed reload keeps the current index, a successful one replaces it.
+    #[test]
+    fn test_reload_in_background() {
+        let dir = std::env::temp_dir();
+        let new_path = dir.join(format!("seismic_handle_{}.index", std::process::id()));
+        let invalid_path = dir.join(format!("seismic_handle_{}.invalid", std::process::id()));
+
+        let new_index = index(vec![(vec![1], vec![7.0])]);
+        fs::write(&new_path, bincode::serialize(&new_index).unwrap()).unwrap();
+        fs::write(&invalid_path, b"not an index").unwrap();
+
+        let handle = Arc::new(IndexHandle::new(index(vec![(vec![0, 1], vec![1.0, 2.0])])));
+        let old_index = handle.current();
+
+        let error = handle
+            .reload_in_background(&invalid_path)
+            .join()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(handle.version(), 0);
+        assert!(Arc::ptr_eq(&handle.current(), &old_index));
+
+        handle
+            .reload_in_background(&new_path)
+            .join()
+            .unwrap()
+            .unwrap();
+        assert_eq!(handle.version(), 1);
+        assert_eq!(*handle.current(), new_index);
+
+        // The snapshot taken before the reload still searches the old index.
+        let config = SearchConfiguration::default().query_cut(1);
+        assert_eq!(
+            old_index.search_with_config(&[1], &[1.0], 1, &config),
+            vec![(2.0, 0)]
+        );
+        assert_eq!(
+            handle.search_with_config(&[1], &[1.0], 1, &config),
+            vec![(7.0, 0)]
+        );
+
+        fs::remove_file(new_path).unwrap();
+        fs::remove_file(invalid_path).unwrap();
+    }
+}
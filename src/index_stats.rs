@@ -0,0 +1,172 @@
+//! This module provides statistics on the structure and the space usage of an
+//! [`InvertedIndex`](crate::InvertedIndex), computed by
+//! [`InvertedIndex::stats`](crate::InvertedIndex::stats).
+//!
+//! The statistics are meant for tuning the building parameters and for capacity planning,
+//! and can be serialized, e.g., to JSON.
+
+use serde::{Deserialize, Serialize};
+
+use crate::inverted_index::Configuration;
+
+/// Statistics on an [`InvertedIndex`](crate::InvertedIndex).
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct IndexStats {
+    pub n_documents: usize,
+    pub dim: usize,
+    pub nnz: usize,
+    /// The total number of postings, i.e., the sum of the lengths of the posting lists.
+    pub n_postings: usize,
+    pub n_posting_lists: usize,
+    pub n_empty_posting_lists: usize,
+    /// The lengths of the non-empty posting lists.
+    pub posting_list_lengths: Histogram,
+    pub n_blocks: usize,
+    /// The number of blocks of each non-empty posting list.
+    pub blocks_per_list: Histogram,
+    /// The number of postings of each block.
+    pub block_sizes: Histogram,
+    /// The number of components of each block summary.
+    pub summary_lengths: Histogram,
+    pub summary_quantization: QuantizationStats,
+    pub space_usage: SpaceBreakdown,
+    pub configuration: Configuration,
+}
+
+/// The distribution of a set of non-negative integers.
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Histogram {
+    pub count: usize,
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+    pub median: usize,
+    pub p90: usize,
+    pub p99: usize,
+    /// The number of values in buckets of exponentially increasing width: `[0, 1)`, `[1, 2)`,
+    /// `[2, 4)`, `[4, 8)`, and so on, up to the bucket of the largest value.
+    pub buckets: Vec<HistogramBucket>,
+}
+
+/// The number of values in the range `[lower, upper)`.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    pub lower: usize,
+    pub upper: usize,
+    pub count: usize,
+}
+
+impl Histogram {
+    /// Computes the distribution of `values`.
+    ///
+    /// # Example
+    /// ```
+    /// use seismic::index_stats::Histogram;
+    ///
+    /// let histogram = Histogram::new(vec![0, 1, 5, 6, 7]);
+    ///
+    /// assert_eq!(histogram.max, 7);
+    /// assert_eq!(histogram.median, 5);
+    /// assert_eq!(histogram.mean, 3.8);
+    ///
+    /// let counts: Vec<_> = histogram.buckets.iter().map(|bucket| bucket.count).collect();
+    /// assert_eq!(counts, vec![1, 1, 0, 3]);
+    /// assert_eq!((histogram.buckets[3].lower, histogram.buckets[3].upper), (4, 8));
+    /// ```
+    pub fn new(values: impl IntoIterator<Item = usize>) -> Self {
+        let mut values: Vec<_> = values.into_iter().collect();
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_unstable();
+
+        let percentile = |p: usize| values[(values.len() - 1) * p / 100];
+        let max = *values.last().unwrap();
+
+        let n_buckets = Self::bucket(max) + 1;
+        let mut buckets: Vec<_> = (0..n_buckets)
+            .map(|i| HistogramBucket {
+                lower: if i == 0 { 0 } else { 1 << (i - 1) },
+                upper: 1 << i,
+                count: 0,
+            })
+            .collect();
+        for &value in values.iter() {
+            buckets[Self::bucket(value)].count += 1;
+        }
+
+        Self {
+            count: values.len(),
+            min: values[0],
+            max,
+            mean: values.iter().sum::<usize>() as f64 / values.len() as f64,
+            median: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            buckets,
+        }
+    }
+
+    #[inline]
+    fn bucket(value: usize) -> usize {
+        (usize::BITS - value.leading_zeros()) as usize
+    }
+}
+
+/// Statistics on the ranges of the values quantized in the block summaries.
+/// Each summary quantizes its values in `[minimum, maximum]` with 256 levels.
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuantizationStats {
+    pub n_summaries: usize,
+    /// The smallest `minimum` among the summaries.
+    pub min_value: f32,
+    /// The largest `maximum` among the summaries.
+    pub max_value: f32,
+    /// The mean of `maximum - minimum` over the summaries.
+    pub mean_range: f32,
+    /// The largest `maximum - minimum` among the summaries.
+    pub max_range: f32,
+}
+
+impl QuantizationStats {
+    /// Computes the statistics from the `(minimum, maximum)` range of each summary.
+    pub fn new(ranges: impl IntoIterator<Item = (f32, f32)>) -> Self {
+        let mut stats = Self {
+            min_value: f32::INFINITY,
+            max_value: f32::NEG_INFINITY,
+            ..Self::default()
+        };
+
+        let mut sum_ranges = 0.0_f64;
+        for (minimum, maximum) in ranges {
+            stats.n_summaries += 1;
+            stats.min_value = stats.min_value.min(minimum);
+            stats.max_value = stats.max_value.max(maximum);
+            stats.max_range = stats.max_range.max(maximum - minimum);
+            sum_ranges += (maximum - minimum) as f64;
+        }
+
+        if stats.n_summaries == 0 {
+            return Self::default();
+        }
+        stats.mean_range = (sum_ranges / stats.n_summaries as f64) as f32;
+
+        stats
+    }
+}
+
+/// The space usage in bytes of each component of an index.
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SpaceBreakdown {
+    pub forward_index: usize,
+    /// The postings of all the posting lists, either packed or compressed.
+    pub postings: usize,
+    pub block_offsets: usize,
+    pub summaries: usize,
+    /// The largest value of each posting list and of the postings pruned away from it,
+    /// used as upper bounds at query time.
+    pub max_values: usize,
+    pub rerank_dataset: usize,
+    pub passage_documents: usize,
+    pub total: usize,
+}
@@ -1,27 +1,40 @@
+use crate::build_observer::{
+    check_cancelled, observe_phase, BuildCancelled, BuildObserver, BuildPhase, SilentObserver,
+};
+use crate::compressed_dataset::{CompressedSparseDataset, ValueQuantization};
 use crate::distances::{dot_product_dense_sparse, dot_product_with_merge};
+use crate::index_stats::{Histogram, IndexStats, QuantizationStats, SpaceBreakdown};
 use crate::sparse_dataset::SparseDatasetMut;
 use crate::topk_selectors::{HeapFaiss, OnlineTopKSelector};
-use crate::utils::{do_random_kmeans_on_docids, prefetch_read_NTA};
+use crate::utils::{
+    decode_varint, do_random_kmeans_on_docids, encode_varint, prefetch_read_NTA, try_decode_varint,
+};
+use crate::validation::Violation;
 use crate::{DataType, QuantizedSummary, SpaceUsage, SparseDataset};
 
-use indicatif::ParallelProgressIterator;
-
-use itertools::Itertools;
+use itertools::{Either, Itertools};
+use num_traits::ToPrimitive;
 
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use std::collections::{HashMap, HashSet};
-use std::time::Instant;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::mem;
 
 #[derive(Default, PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct InvertedIndex<T>
 where
     T: DataType,
 {
-    forward_index: SparseDataset<T>,
+    forward_index: ForwardIndex<T>,
     posting_lists: Box<[PostingList]>,
     config: Configuration,
+    // Original-precision vectors used to rerank candidates, see `with_rerank_dataset`.
+    rerank_dataset: Option<SparseDataset<f32>>,
+    // The document of each indexed passage, see `with_passage_documents`.
+    passage_documents: Option<Box<[usize]>>,
 }
 
 impl<T> SpaceUsage for InvertedIndex<T>
@@ -37,7 +50,17 @@ where
             .map(|list| list.space_usage_byte())
             .sum();
 
-        forward + postings
+        let rerank = self
+            .rerank_dataset
+            .as_ref()
+            .map_or(0, |dataset| dataset.space_usage_byte());
+
+        let passage_documents = self
+            .passage_documents
+            .as_ref()
+            .map_or(0, |passage_documents| passage_documents.space_usage_byte());
+
+        forward + postings + rerank + passage_documents
     }
 }
 
@@ -62,6 +85,9 @@ pub struct Configuration {
     pruning: PruningStrategy,
     blocking: BlockingStrategy,
     summarization: SummarizationStrategy,
+    forward_index: ForwardIndexStrategy,
+    postings: PostingsStrategy,
+    block_order: BlockOrder,
 }
 
 impl Configuration {
@@ -82,10 +108,152 @@ impl Configuration {
 
         self
     }
+
+    pub fn forward_index_strategy(mut self, forward_index: ForwardIndexStrategy) -> Self {
+        self.forward_index = forward_index;
+
+        self
+    }
+
+    pub fn postings_strategy(mut self, postings: PostingsStrategy) -> Self {
+        self.postings = postings;
+
+        self
+    }
+
+    pub fn block_order(mut self, block_order: BlockOrder) -> Self {
+        self.block_order = block_order;
+
+        self
+    }
+
+    /// Returns the pruning strategy.
+    #[must_use]
+    pub fn pruning(&self) -> &PruningStrategy {
+        &self.pruning
+    }
+
+    /// Returns the blocking strategy.
+    #[must_use]
+    pub fn blocking(&self) -> &BlockingStrategy {
+        &self.blocking
+    }
+
+    /// Returns the summarization strategy.
+    #[must_use]
+    pub fn summarization(&self) -> &SummarizationStrategy {
+        &self.summarization
+    }
+
+    /// Returns the forward index strategy.
+    #[must_use]
+    pub fn forward_index(&self) -> &ForwardIndexStrategy {
+        &self.forward_index
+    }
+
+    /// Returns the postings strategy.
+    #[must_use]
+    pub fn postings(&self) -> &PostingsStrategy {
+        &self.postings
+    }
+
+    /// Returns the order of the blocks.
+    #[must_use]
+    pub fn block_ordering(&self) -> &BlockOrder {
+        &self.block_order
+    }
+}
+
+/// This struct contains the parameters that regulate a search and trade-off
+/// efficiency and accuracy. See [`InvertedIndex::search_with_config`].
+/// - `query_pruning`: Selects the components of the query whose posting lists are evaluated. See [`QueryPruning`].
+/// - `heap_factor`: A block is skipped if its estimated dot product is smaller than `heap_factor` times the smallest dot product in the current top-k.
+/// - `traversal`: The order in which blocks are evaluated. See [`BlockTraversal`].
+/// - `block_scan`: How the blocks of a posting list are scanned with the `ListByList` traversal. See [`BlockScan`].
+/// - `dynamic_termination`: If `true`, posting lists are visited by decreasing query value and the search stops as soon as the sum of the query values times the largest value of the remaining lists, and of the lists of the components dropped by `query_pruning`, cannot beat the smallest dot product in the current top-k. A vector not found yet may still have a posting in the lists already visited, if it has been pruned away or if its block has been skipped by the `heap_factor` test, so the sum also includes the query values times the largest value pruned away from each of them, or their largest value if some of their blocks have been skipped. Terminating does not change the results, with any `heap_factor`. With `DocumentAggregation::SumTopPassages`, the bound does not hold for documents and dynamic termination is ignored.
+/// - `rerank`: If `Some(n_candidates)`, the search collects the top `n_candidates` results and rescores them with the original-precision vectors before returning the top-k. The index must store them, see [`InvertedIndex::with_rerank_dataset`]. It cannot be combined with `document_aggregation`.
+/// - `document_aggregation`: If given, the indexed vectors are passages and the search returns the top-k distinct documents, scored by aggregating the scores of their passages. See [`DocumentAggregation`]. The index must store the document of each passage, see [`InvertedIndex::with_passage_documents`].
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct SearchConfiguration {
+    query_pruning: QueryPruning,
+    heap_factor: f32,
+    traversal: BlockTraversal,
+    block_scan: BlockScan,
+    dynamic_termination: bool,
+    rerank: Option<usize>,
+    document_aggregation: Option<DocumentAggregation>,
+}
+
+impl Default for SearchConfiguration {
+    fn default() -> Self {
+        Self {
+            query_pruning: QueryPruning::default(),
+            heap_factor: 0.7,
+            traversal: BlockTraversal::default(),
+            block_scan: BlockScan::default(),
+            dynamic_termination: false,
+            rerank: None,
+            document_aggregation: None,
+        }
+    }
+}
+
+impl SearchConfiguration {
+    pub fn query_pruning(mut self, query_pruning: QueryPruning) -> Self {
+        self.query_pruning = query_pruning;
+
+        self
+    }
+
+    /// Shorthand for a [`QueryPruning::FixedSize`] query pruning with `query_cut` components.
+    pub fn query_cut(self, query_cut: usize) -> Self {
+        self.query_pruning(QueryPruning::FixedSize {
+            n_components: query_cut,
+        })
+    }
+
+    pub fn heap_factor(mut self, heap_factor: f32) -> Self {
+        self.heap_factor = heap_factor;
+
+        self
+    }
+
+    pub fn traversal(mut self, traversal: BlockTraversal) -> Self {
+        self.traversal = traversal;
+
+        self
+    }
+
+    pub fn block_scan(mut self, block_scan: BlockScan) -> Self {
+        self.block_scan = block_scan;
+
+        self
+    }
+
+    pub fn dynamic_termination(mut self, dynamic_termination: bool) -> Self {
+        self.dynamic_termination = dynamic_termination;
+
+        self
+    }
+
+    pub fn rerank(mut self, n_candidates: Option<usize>) -> Self {
+        self.rerank = n_candidates;
+
+        self
+    }
+
+    pub fn document_aggregation(mut self, aggregation: Option<DocumentAggregation>) -> Self {
+        self.document_aggregation = aggregation;
+
+        self
+    }
 }
 
 const THRESHOLD_BINARY_SEARCH: usize = 10;
 
+// The number of documents distributed between two progress notifications.
+const PROGRESS_STEP: usize = 1 << 16;
+
 impl<T> InvertedIndex<T>
 where
     T: PartialOrd + DataType,
@@ -118,582 +286,4474 @@ where
         query_cut: usize,
         heap_factor: f32,
     ) -> Vec<(f32, usize)> {
+        self.search_with_config(
+            query_components,
+            query_values,
+            k,
+            &SearchConfiguration::default()
+                .query_cut(query_cut)
+                .heap_factor(heap_factor),
+        )
+    }
+
+    /// Searches the top-`k` results of the query with the parameters in `config`.
+    ///
+    /// # Panics
+    /// Panics if `config` requires reranking but the index does not store the original-precision vectors,
+    /// if `config` has a `document_aggregation` but the index does not store the documents of the passages,
+    /// or if `config` has both a `rerank` and a `document_aggregation`, which cannot be combined.
+    #[must_use]
+    pub fn search_with_config(
+        &self,
+        query_components: &[u16],
+        query_values: &[f32],
+        k: usize,
+        config: &SearchConfiguration,
+    ) -> Vec<(f32, usize)> {
+        if k == 0 {
+            return Vec::new();
+        }
+
         let mut query = vec![0.0; self.dim()];
 
         for (&i, &v) in query_components.iter().zip(query_values) {
             query[i as usize] = v;
         }
-        let mut heap = HeapFaiss::new(k);
-        let mut visited = HashSet::with_capacity(query_cut * 5000); // 5000 should be n_postings
 
-        // Sort query terms by score and evaluate the posting list only for the top ones
-        for (&component_id, &_value) in query_components
-            .iter()
-            .zip(query_values)
-            .sorted_unstable_by(|a, b| b.1.partial_cmp(a.1).unwrap())
-            .take(query_cut)
-        {
-            self.posting_lists[component_id as usize].search(
+        // With reranking we collect a larger pool of candidates
+        let n_candidates = config.rerank.map_or(k, |n_candidates| n_candidates.max(k));
+
+        // Evaluate the posting lists only for the query terms selected by the pruning strategy
+        let selected_components = config
+            .query_pruning
+            .select_components(query_components, query_values);
+
+        if let Some(aggregation) = &config.document_aggregation {
+            assert!(
+                config.rerank.is_none(),
+                "Reranking is not supported when results are grouped by document"
+            );
+            let passage_documents = self
+                .passage_documents
+                .as_ref()
+                .expect("The index does not store the documents of the passages");
+
+            // The bound of dynamic termination holds for a document only if it is scored with
+            // its best passage, as a partially evaluated document may gain up to `m` passages.
+            let config = match *aggregation {
+                DocumentAggregation::SumTopPassages { m } if m > 1 => {
+                    config.clone().dynamic_termination(false)
+                }
+                _ => config.clone(),
+            };
+
+            let mut heap =
+                DocumentHeap::new(k, aggregation, passage_documents, &self.forward_index);
+            self.collect_candidates(
                 &query,
                 query_components,
                 query_values,
+                &selected_components,
                 k,
-                heap_factor,
+                &config,
                 &mut heap,
-                &mut visited,
-                &self.forward_index,
             );
+
+            return heap.topk();
         }
 
-        heap.topk()
-            .iter()
-            .map(|&(dot, offset)| (dot.abs(), self.forward_index.offset_to_id(offset)))
-            .collect()
-    }
+        let mut heap = HeapFaiss::new(n_candidates);
+        self.collect_candidates(
+            &query,
+            query_components,
+            query_values,
+            &selected_components,
+            n_candidates,
+            config,
+            &mut heap,
+        );
 
-    /// `n_postings`: minimum number of postings to select for each component
-    pub fn build(dataset: SparseDataset<T>, config: Configuration) -> Self {
-        // Distribute pairs (score, doc_id) to corresponding components.
-        // We use pairs because later each posting list will be sorted by score
-        // by the pruning strategy.
+        let results = heap
+            .topk()
+            .into_iter()
+            .map(|(dot, offset)| (dot.abs(), self.forward_index.offset_to_id(offset)));
 
-        print!("\tDistributing postings ");
-        let time = Instant::now();
-        let mut inverted_pairs = Vec::with_capacity(dataset.dim());
-        for _ in 0..dataset.dim() {
-            inverted_pairs.push(Vec::new());
+        if config.rerank.is_some() {
+            self.rerank(&query, results.map(|(_, id)| id), k)
+        } else {
+            results.collect()
         }
+    }
+
+    // Visits the posting lists of the selected components and pushes the evaluated vectors
+    // into `heap`, according to the traversal in `config`.
+    #[allow(clippy::too_many_arguments)]
+    fn collect_candidates<H: SearchHeap>(
+        &self,
+        query: &[f32],
+        query_components: &[u16],
+        query_values: &[f32],
+        selected_components: &[u16],
+        n_candidates: usize,
+        config: &SearchConfiguration,
+        heap: &mut H,
+    ) {
+        let mut visited = HashSet::with_capacity(selected_components.len() * 5000); // 5000 should be n_postings
+
+        match config.traversal {
+            BlockTraversal::ListByList => {
+                // Upper bounds to the contribution of the lists still to be visited
+                let remaining_bounds = if config.dynamic_termination {
+                    self.remaining_upper_bounds(query, query_components, selected_components)
+                } else {
+                    Vec::new()
+                };
+
+                // Upper bound to the contribution of the lists already visited to the vectors
+                // not found in them
+                let mut visited_bound = 0.0;
+
+                for (i, &component_id) in selected_components.iter().enumerate() {
+                    if config.dynamic_termination
+                        && heap.len() == n_candidates
+                        && remaining_bounds[i] + visited_bound <= -heap.top()
+                    {
+                        break;
+                    }
+
+                    let posting_list = &self.posting_lists[component_id as usize];
+                    let skipped = posting_list.search(
+                        query,
+                        query_components,
+                        query_values,
+                        n_candidates,
+                        config.heap_factor,
+                        config.block_scan,
+                        heap,
+                        &mut visited,
+                        &self.forward_index,
+                    );
 
-        for (doc_id, (components, values)) in dataset.iter().enumerate() {
-            for (&c, &score) in components.iter().zip(values) {
-                inverted_pairs[c as usize].push((score, doc_id));
+                    // A vector may be in a skipped block or pruned away from the list.
+                    let list_bound = if skipped {
+                        posting_list.max_value.max(posting_list.pruned_value)
+                    } else {
+                        posting_list.pruned_value
+                    };
+                    visited_bound += (query[component_id as usize] * list_bound).max(0.0);
+                }
             }
+            BlockTraversal::BestFirst => self.search_best_first(
+                query,
+                query_components,
+                query_values,
+                selected_components,
+                n_candidates,
+                config.heap_factor,
+                heap,
+                &mut visited,
+            ),
         }
+    }
 
-        let elapsed = time.elapsed();
-        println!("{} secs", elapsed.as_secs());
+    /// Searches all the vectors whose dot product with the query is at least `threshold`.
+    ///
+    /// The posting lists of all the query components are visited, and a block is evaluated only if
+    /// its summary estimates a dot product of at least `threshold`. As summaries are approximated,
+    /// results may be missed.
+    /// Results are sorted by decreasing dot product. If `max_results` is given, only the best
+    /// `max_results` of them are returned.
+    #[must_use]
+    pub fn search_range(
+        &self,
+        query_components: &[u16],
+        query_values: &[f32],
+        threshold: f32,
+        max_results: Option<usize>,
+    ) -> Vec<(f32, usize)> {
+        let mut query = vec![0.0; self.dim()];
 
-        // Apply the selected pruning strategy
+        for (&i, &v) in query_components.iter().zip(query_values) {
+            query[i as usize] = v;
+        }
 
-        print!("\tPruning postings ");
-        let time = Instant::now();
+        let mut results = Vec::new();
+        let mut visited = HashSet::new();
+        let mut buffer = Vec::new();
 
-        match config.pruning {
-            PruningStrategy::FixedSize { n_postings } => {
-                Self::fixed_pruning(&mut inverted_pairs, n_postings)
-            }
+        for &component_id in query_components.iter() {
+            let posting_list = &self.posting_lists[component_id as usize];
+            let dots = posting_list
+                .summaries
+                .matmul_with_query(query_components, query_values);
 
-            PruningStrategy::GlobalThreshold {
-                n_postings,
-                max_fraction,
-            } => {
-                Self::global_threshold_pruning(&mut inverted_pairs, n_postings);
-                Self::fixed_pruning(
-                    &mut inverted_pairs,
-                    (n_postings as f32 * max_fraction) as usize,
-                ) // cuts too long lists
+            for (block_id, &dot) in dots.iter().enumerate() {
+                if dot < threshold {
+                    continue;
+                }
+
+                let packed_block =
+                    posting_list.packed_block(block_id, &self.forward_index, &mut buffer);
+                for &pack in packed_block {
+                    let (offset, len) = PostingList::unpack_offset_len(pack);
+                    if !visited.insert(offset) {
+                        continue;
+                    }
+
+                    let distance = self.forward_index.dot_product_with_offset(
+                        &query,
+                        query_components,
+                        query_values,
+                        offset,
+                        len,
+                    );
+                    if distance >= threshold {
+                        results.push((distance, offset));
+                    }
+                }
             }
         }
 
-        let elapsed = time.elapsed();
-        println!("{} secs", elapsed.as_secs());
+        results.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+        if let Some(max_results) = max_results {
+            results.truncate(max_results);
+        }
 
-        print!("\tBuilding summaries ");
-        let time = Instant::now();
+        results
+            .into_iter()
+            .map(|(dot, offset)| (dot, self.forward_index.offset_to_id(offset)))
+            .collect()
+    }
 
-        println!("\tNumber of posting lists: {}", inverted_pairs.len());
-        // Build summaries and blocks for each posting list
-        let posting_lists: Vec<_> = inverted_pairs
-            .par_iter()
-            .progress_count(inverted_pairs.len() as u64)
-            .enumerate()
-            .map(|(_component_id, posting_list)| {
-                //println!("\tDealing with component {_component_id}");
-                PostingList::build(&dataset, posting_list, &config)
+    // Scores the summaries of the blocks of all the selected lists and evaluates the blocks
+    // by decreasing estimated dot product. The traversal stops at the first block that
+    // fails the `heap_factor` test, as all the following ones would fail it as well.
+    #[allow(clippy::too_many_arguments)]
+    fn search_best_first<H: SearchHeap>(
+        &self,
+        query: &[f32],
+        query_components: &[u16],
+        query_values: &[f32],
+        selected_components: &[u16],
+        k: usize,
+        heap_factor: f32,
+        heap: &mut H,
+        visited: &mut HashSet<usize>,
+    ) {
+        let mut queue: BinaryHeap<_> = selected_components
+            .iter()
+            .flat_map(|&component_id| {
+                self.posting_lists[component_id as usize]
+                    .summaries
+                    .matmul_with_query(query_components, query_values)
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(block_id, dot)| ScoredBlock {
+                        dot,
+                        component_id,
+                        block_id,
+                    })
             })
             .collect();
 
-        let elapsed = time.elapsed();
-        println!("{} secs", elapsed.as_secs());
+        let mut buffer = Vec::new();
+        while let Some(block) = queue.pop() {
+            if heap.len() == k && block.dot < -heap_factor * heap.top() {
+                break;
+            }
 
-        Self {
-            forward_index: dataset,
-            posting_lists: posting_lists.into_boxed_slice(),
-            config,
+            if let Some(next_block) = queue.peek() {
+                self.posting_lists[next_block.component_id as usize]
+                    .prefetch_block(next_block.block_id);
+            }
+
+            let posting_list = &self.posting_lists[block.component_id as usize];
+            posting_list.evaluate_posting_block(
+                query,
+                query_components,
+                query_values,
+                posting_list.packed_block(block.block_id, &self.forward_index, &mut buffer),
+                heap,
+                visited,
+                &self.forward_index,
+            );
         }
     }
 
-    // Implementation of the pruning strategy that selects the top-`n_postings` from each posting list
-    fn fixed_pruning(inverted_pairs: &mut Vec<Vec<(T, usize)>>, n_postings: usize) {
-        inverted_pairs.par_iter_mut().for_each(|posting_list| {
-            posting_list.sort_unstable_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
-
-            posting_list.truncate(n_postings);
+    // Returns, for each position i, the sum of query value times largest value of the lists of
+    // `selected_components[i..]` and of the query components which are not selected.
+    // The vectors found in these lists may still get a score from the components not selected.
+    fn remaining_upper_bounds(
+        &self,
+        query: &[f32],
+        query_components: &[u16],
+        selected_components: &[u16],
+    ) -> Vec<f32> {
+        let bound =
+            |c: u16| (query[c as usize] * self.posting_lists[c as usize].max_value).max(0.0);
 
-            posting_list.shrink_to_fit();
-        })
-    }
+        let selected: HashSet<_> = selected_components.iter().collect();
+        let not_selected: f32 = query_components
+            .iter()
+            .filter(|c| !selected.contains(c))
+            .map(|&c| bound(c))
+            .sum();
 
-    // Implementation of the pruning strategy that selects a threshold such that survives on average `n_postings` for each posting list
-    fn global_threshold_pruning(inverted_pairs: &mut [Vec<(T, usize)>], n_postings: usize) {
-        let tot_postings = inverted_pairs.len() * n_postings; // overall number of postings to select
+        let mut bounds: Vec<_> = selected_components.iter().map(|&c| bound(c)).collect();
+        bounds.push(not_selected);
 
-        // for every posting we create the tuple <score, docid, id_posting_list>
-        let mut postings = Vec::<(T, usize, u16)>::new();
-        for (id, posting_list) in inverted_pairs.iter_mut().enumerate() {
-            for (score, docid) in posting_list.iter() {
-                postings.push((*score, *docid, id as u16));
-            }
-            posting_list.clear();
+        for i in (0..bounds.len() - 1).rev() {
+            bounds[i] += bounds[i + 1];
         }
+        bounds.pop();
+
+        bounds
+    }
+
+    // Rescores the `candidates` with the original-precision vectors and returns the top-`k`.
+    fn rerank(
+        &self,
+        query: &[f32],
+        candidates: impl Iterator<Item = usize>,
+        k: usize,
+    ) -> Vec<(f32, usize)> {
+        let dataset = self
+            .rerank_dataset
+            .as_ref()
+            .expect("Reranking requires the original-precision vectors, see `with_rerank_dataset`");
 
-        let tot_postings = tot_postings.min(postings.len() - 1);
+        let mut results: Vec<_> = candidates
+            .map(|id| {
+                let (v_components, v_values) = dataset.get(id);
+                (dot_product_dense_sparse(query, v_components, v_values), id)
+            })
+            .collect();
 
-        postings.select_nth_unstable_by(tot_postings, |a, b| b.0.partial_cmp(&a.0).unwrap());
+        results.sort_unstable_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+        results.truncate(k);
 
-        for (score, docid, id_posting) in postings.into_iter().take(tot_postings) {
-            inverted_pairs[id_posting as usize].push((score, docid));
-        }
+        results
     }
 
-    /// Returns the id of the largest component, i.e., the dimensionality of the vectors in the dataset.
+    /// Stores the original-precision vectors of the indexed dataset, which are used to
+    /// rerank the candidates of a search. See [`SearchConfiguration::rerank`].
+    ///
+    /// The `dataset` is typically the one given to [`InvertedIndex::build`] before quantizing it.
+    ///
+    /// # Panics
+    /// Panics if `dataset` does not have the same number of vectors as the index.
     #[must_use]
-    pub fn dim(&self) -> usize {
-        self.forward_index.dim()
+    pub fn with_rerank_dataset(mut self, dataset: SparseDataset<f32>) -> Self {
+        assert_eq!(
+            dataset.len(),
+            self.len(),
+            "The rerank dataset must have the same number of vectors as the index"
+        );
+
+        self.rerank_dataset = Some(dataset);
+
+        self
     }
 
-    /// Returns the number of non-zero components in the dataset.
+    /// Removes and returns the original-precision vectors stored in the index, if any.
+    /// This is useful to serialize the index and the vectors separately.
+    pub fn take_rerank_dataset(&mut self) -> Option<SparseDataset<f32>> {
+        self.rerank_dataset.take()
+    }
+
+    /// Returns the configuration used to build the index.
     #[must_use]
-    pub fn nnz(&self) -> usize {
-        self.forward_index.nnz()
+    pub fn config(&self) -> &Configuration {
+        &self.config
     }
 
-    /// Returns the number of vectors in the dataset
+    /// Checks if the index stores the original-precision vectors to rerank candidates.
     #[must_use]
-    pub fn len(&self) -> usize {
-        self.forward_index.len()
+    pub fn has_rerank_dataset(&self) -> bool {
+        self.rerank_dataset.is_some()
     }
 
-    /// Checks if the dataset is empty.
+    /// Stores the document of each indexed vector, so that searches can group the results
+    /// by document. See [`SearchConfiguration::document_aggregation`].
+    ///
+    /// # Panics
+    /// Panics if `passage_documents` does not have an entry for every vector in the index.
     #[must_use]
-    pub fn is_empty(&self) -> bool {
-        self.forward_index.len() == 0
+    pub fn with_passage_documents(mut self, passage_documents: Vec<usize>) -> Self {
+        assert_eq!(
+            passage_documents.len(),
+            self.len(),
+            "There must be a document for every vector in the index"
+        );
+
+        self.passage_documents = Some(passage_documents.into_boxed_slice());
+
+        self
     }
-}
 
-// Instead of string doc_ids we store their offsets in the forward_index and the lengths of the vectors
-// This allows us to save the random acceses that would be needed to access exactly these values from the
-// forward index. The values of each doc are packed into a single u64 in `packed_postings`. We use 48 bits for the offset and 16 bits for the lenght. This choice limits the size of the dataset to be 1<<48-1.
-// We use the forward index to convert the offsets of the top-k back to the id of the corresponding documents.
-#[derive(Default, PartialEq, Debug, Clone, Serialize, Deserialize)]
-struct PostingList {
-    // postings: Box<[usize]>,
-    packed_postings: Box<[u64]>,
-    block_offsets: Box<[usize]>,
-    // summaries: SparseDataset<f16>,
-    summaries: QuantizedSummary,
-}
+    /// Checks if the index stores the document of each vector.
+    #[must_use]
+    pub fn has_passage_documents(&self) -> bool {
+        self.passage_documents.is_some()
+    }
 
-impl SpaceUsage for PostingList {
-    fn space_usage_byte(&self) -> usize {
-        self.packed_postings.space_usage_byte()
-            + self.block_offsets.space_usage_byte()
-            + self.summaries.space_usage_byte()
+    /// `n_postings`: minimum number of postings to select for each component
+    pub fn build(dataset: SparseDataset<T>, config: Configuration) -> Self {
+        Self::build_with_observer(dataset, config, &SilentObserver)
+            .expect("The silent observer never cancels the construction")
     }
-}
 
-impl PostingList {
-    #[inline]
-    fn pack_offset_len(offset: usize, len: usize) -> u64 {
-        ((offset as u64) << 16) | (len as u64)
+    /// Same as [`InvertedIndex::build`], but notifies `observer` of the progress of the construction.
+    ///
+    /// # Errors
+    /// Returns [`BuildCancelled`] if `observer` cancels the construction.
+    ///
+    /// # Example
+    /// ```
+    /// use seismic::build_observer::{BuildObserver, BuildPhase};
+    /// use seismic::inverted_index::Configuration;
+    /// use seismic::{InvertedIndex, SparseDataset};
+    /// use std::sync::Mutex;
+    /// use std::time::Duration;
+    ///
+    /// #[derive(Default)]
+    /// struct PhaseLog(Mutex<Vec<BuildPhase>>);
+    ///
+    /// impl BuildObserver for PhaseLog {
+    ///     fn phase_finished(&self, phase: BuildPhase, _elapsed: Duration) {
+    ///         self.0.lock().unwrap().push(phase);
+    ///     }
+    /// }
+    ///
+    /// let data = vec![(vec![0, 2], vec![1.0, 2.0]), (vec![1, 3], vec![4.0, 5.0])];
+    /// let dataset: SparseDataset<f32> = data.into_iter().collect();
+    /// let log = PhaseLog::default();
+    /// let index = InvertedIndex::build_with_observer(dataset, Configuration::default(), &log).unwrap();
+    ///
+    /// assert_eq!(log.0.lock().unwrap().last(), Some(&BuildPhase::BuildingPostingLists));
+    /// ```
+    pub fn build_with_observer(
+        dataset: SparseDataset<T>,
+        config: Configuration,
+        observer: &dyn BuildObserver,
+    ) -> Result<Self, BuildCancelled> {
+        Self::build_with_list_weights(dataset, config, None, observer)
     }
 
-    #[inline]
-    fn unpack_offset_len(pack: u64) -> (usize, usize) {
-        ((pack >> 16) as usize, (pack & (u16::MAX as u64)) as usize)
+    /// Builds the index by adapting the pruning and the blocking of each posting list
+    /// to a sample of the queries in `query_log`.
+    ///
+    /// The postings kept by the pruning strategy in `config` are redistributed among the
+    /// posting lists proportionally to how many queries contain their component, so that
+    /// frequently queried components get longer posting lists and components which are
+    /// never queried get shorter ones. The total number of postings does not change, and with
+    /// `GlobalThreshold` pruning no list gets more than `max_fraction * n_postings` postings.
+    /// The blocks of the lists of frequent components are also finer, while the total
+    /// number of blocks stays roughly the same.
+    ///
+    /// The configuration stored in the index reports the pruning actually applied as a
+    /// `GlobalThreshold` strategy, with the average number of postings per list and
+    /// the `max_fraction` of the longest list.
+    pub fn build_with_query_log(
+        dataset: SparseDataset<T>,
+        config: Configuration,
+        query_log: &SparseDataset<f32>,
+    ) -> Self {
+        Self::build_with_query_log_with_observer(dataset, config, query_log, &SilentObserver)
+            .expect("The silent observer never cancels the construction")
     }
 
-    #[allow(clippy::too_many_arguments)]
+    /// Same as [`InvertedIndex::build_with_query_log`], but notifies `observer` of the progress
+    /// of the construction.
+    ///
+    /// # Errors
+    /// Returns [`BuildCancelled`] if `observer` cancels the construction.
+    pub fn build_with_query_log_with_observer(
+        dataset: SparseDataset<T>,
+        config: Configuration,
+        query_log: &SparseDataset<f32>,
+        observer: &dyn BuildObserver,
+    ) -> Result<Self, BuildCancelled> {
+        // Add-one smoothing: components which are never queried still get some postings.
+        let mut weights = vec![1.0_f32; dataset.dim()];
+        for (components, _) in query_log.iter() {
+            for &c in components {
+                if let Some(weight) = weights.get_mut(c as usize) {
+                    *weight += 1.0;
+                }
+            }
+        }
+
+        Self::build_with_list_weights(dataset, config, Some(weights), observer)
+    }
+
+    /// Builds the index so that its space usage does not exceed `budget_byte` bytes.
+    ///
+    /// The space of the forward index is computed in advance. The cost of a posting,
+    /// including its share of the blocks and of the summaries, is estimated by building a
+    /// sample of the posting lists with the blocking and summarization strategies in `config`.
+    /// The number of postings of the pruning strategy is then chosen to fill the remaining budget.
+    /// If the estimate is too optimistic, the index is pruned further until it fits: with a new
+    /// estimate for the first few times, then by halving the number of postings, so the index is
+    /// pruned at most a few times plus the logarithm of the number of postings. Each time, only
+    /// the posting lists which lose postings are built again.
+    ///
+    /// Only the pruning is adapted to the budget: the blocking and summarization strategies in
+    /// `config` are applied as they are.
+    ///
+    /// With `FixedSize` and `GlobalThreshold` pruning, `n_postings` is replaced by the chosen value.
+    /// The other pruning strategies are applied as they are and, if they keep too many postings,
+    /// the longest posting lists are shortened.
+    /// The configuration stored in the index reports the actual pruning strategy. Shortened lists
+    /// are reported as a `GlobalThreshold` strategy, with the average number of postings per list
+    /// and the `max_fraction` of the longest list.
+    ///
+    /// # Panics
+    /// Panics if the forward index alone does not fit into `budget_byte`, or if the index
+    /// does not fit even when all the posting lists are empty, i.e., their blocks and summaries
+    /// alone exceed the budget.
+    pub fn build_with_budget(
+        dataset: SparseDataset<T>,
+        config: Configuration,
+        budget_byte: usize,
+    ) -> Self {
+        Self::build_with_budget_with_observer(dataset, config, budget_byte, &SilentObserver)
+            .expect("The silent observer never cancels the construction")
+    }
+
+    /// Same as [`InvertedIndex::build_with_budget`], but notifies `observer` of the progress
+    /// of the construction.
+    ///
+    /// # Errors
+    /// Returns [`BuildCancelled`] if `observer` cancels the construction.
+    ///
+    /// # Panics
+    /// Panics as [`InvertedIndex::build_with_budget`].
+    pub fn build_with_budget_with_observer(
+        dataset: SparseDataset<T>,
+        config: Configuration,
+        budget_byte: usize,
+        observer: &dyn BuildObserver,
+    ) -> Result<Self, BuildCancelled> {
+        const MAX_ATTEMPTS: usize = 4;
+        const N_SAMPLE_LISTS: usize = 256;
+
+        let mut inverted_pairs = Self::distribute_postings(&dataset, &config.pruning, observer)?;
+        let (forward_index, original_dataset) =
+            Self::build_forward_index(dataset, &config.forward_index, observer)?;
+        let dataset = original_dataset
+            .as_ref()
+            .unwrap_or_else(|| forward_index.as_plain().unwrap());
+
+        let forward_bytes = forward_index.space_usage_byte();
+        assert!(
+            forward_bytes < budget_byte,
+            "The forward index alone takes {forward_bytes} bytes, more than the budget of {budget_byte} bytes"
+        );
+
+        // Strategies without a number of postings are applied once, then shortened if needed.
+        match config.pruning {
+            PruningStrategy::FixedSize { .. } | PruningStrategy::GlobalThreshold { .. } => {}
+            _ => observe_phase(observer, BuildPhase::PruningPostings, None, || {
+                Self::prune(&mut inverted_pairs, &config.pruning);
+                Ok(())
+            })?,
+        }
+
+        // Estimates the cost of a posting on a sample of the posting lists.
+        let empty_list_bytes =
+            PostingList::build(dataset, &forward_index, &[], &config).space_usage_byte();
+        let trial_n_postings = match config.pruning {
+            PruningStrategy::FixedSize { n_postings }
+            | PruningStrategy::GlobalThreshold { n_postings, .. } => n_postings,
+            _ => usize::MAX,
+        };
+        let step = (inverted_pairs.len() / N_SAMPLE_LISTS).max(1);
+        let (sample_bytes, sample_postings) = inverted_pairs
+            .par_iter()
+            .step_by(step)
+            .filter(|posting_list| !posting_list.is_empty())
+            .map(|posting_list| {
+                let mut posting_list = posting_list.clone();
+                posting_list.sort_unstable_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+                posting_list.truncate(trial_n_postings);
+                let list = PostingList::build(dataset, &forward_index, &posting_list, &config);
+
+                (
+                    list.space_usage_byte() - empty_list_bytes,
+                    posting_list.len(),
+                )
+            })
+            .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+        let posting_bytes = if sample_postings > 0 {
+            sample_bytes as f64 / sample_postings as f64
+        } else {
+            mem::size_of::<u64>() as f64
+        };
+
+        let lists_budget = (budget_byte - forward_bytes)
+            .saturating_sub(empty_list_bytes * inverted_pairs.len())
+            as f64;
+        let mut tot_postings = (lists_budget / posting_bytes) as usize;
+
+        let mut attempt = 0;
+        let mut previous_lists = None;
+        let (pruning, mut posting_lists) = loop {
+            let pruning = observe_phase(observer, BuildPhase::PruningPostings, None, || {
+                Ok(Self::budget_pruning(
+                    &mut inverted_pairs,
+                    &config.pruning,
+                    tot_postings,
+                ))
+            })?;
+            let posting_lists = match previous_lists.take() {
+                None => Self::build_posting_lists(
+                    dataset,
+                    &forward_index,
+                    &inverted_pairs,
+                    &config,
+                    None,
+                    observer,
+                ),
+                Some(previous_lists) => Self::rebuild_shortened_lists(
+                    dataset,
+                    &forward_index,
+                    &inverted_pairs,
+                    &config,
+                    previous_lists,
+                    observer,
+                ),
+            }?;
+
+            let lists_bytes: usize = posting_lists
+                .iter()
+                .map(|list| list.space_usage_byte())
+                .sum();
+            if forward_bytes + lists_bytes <= budget_byte {
+                break (pruning, posting_lists);
+            }
+            assert!(
+                tot_postings > 0,
+                "The index takes {} bytes without postings, more than the budget of {budget_byte} bytes",
+                forward_bytes + lists_bytes
+            );
+
+            attempt += 1;
+            tot_postings = if attempt < MAX_ATTEMPTS {
+                let postings_bytes =
+                    lists_bytes.saturating_sub(empty_list_bytes * inverted_pairs.len()) as f64;
+                let n_postings: usize = inverted_pairs.iter().map(|list| list.len()).sum();
+                let estimate = (0.98 * lists_budget / postings_bytes * n_postings as f64) as usize;
+                estimate.min(tot_postings - 1)
+            } else {
+                tot_postings / 2
+            };
+            previous_lists = Some(posting_lists);
+        };
+
+        Self::set_pruned_values(&mut posting_lists, &forward_index);
+
+        Ok(Self {
+            forward_index,
+            posting_lists: posting_lists.into_boxed_slice(),
+            config: config.pruning_strategy(pruning),
+            rerank_dataset: None,
+            passage_documents: None,
+        })
+    }
+
+    /// Merges two indexes into one indexing the vectors of `a` followed by those of `b`,
+    /// i.e., the vector with id `i` in `b` has id `a.len() + i` in the merged index.
+    ///
+    /// The forward indexes are concatenated, and the postings of each component are combined
+    /// and pruned again with the pruning strategy of the configuration. Only the posting lists
+    /// whose postings change are clustered and summarized again, the others are reused with their
+    /// postings remapped to the merged forward index.
+    ///
+    /// With `FixedSize`, `Threshold`, and `DocumentCentric` pruning and an uncompressed forward
+    /// index, the merged index has the same postings as an index built from scratch on the
+    /// concatenation of the two datasets. With the other strategies it is an approximation, since
+    /// the postings pruned away from `a` and `b` are lost. If the forward index is compressed,
+    /// postings are pruned again, clustered, and summarized on its dequantized values, so the
+    /// postings kept near the pruning threshold may differ from those of a build from scratch.
+    ///
+    /// The rerank datasets are concatenated, and so are the documents of the passages, where
+    /// the documents of `b` are numbered after those of `a`.
+    ///
+    /// # Errors
+    /// Returns a [`MergeError`] if the two indexes have been built with different configurations,
+    /// or if only one of them has a rerank dataset or the documents of its passages.
+    ///
+    /// # Example
+    /// ```
+    /// use seismic::inverted_index::{Configuration, PruningStrategy};
+    /// use seismic::{InvertedIndex, SparseDataset};
+    ///
+    /// let config = Configuration::default().pruning_strategy(PruningStrategy::FixedSize { n_postings: 10 });
+    ///
+    /// let data = vec![(vec![0, 2], vec![1.0, 2.0]), (vec![1, 3], vec![4.0, 5.0])];
+    /// let a = InvertedIndex::build(data.into_iter().collect::<SparseDataset<f32>>(), config.clone());
+    /// let data = vec![(vec![1, 4], vec![3.0, 1.0])];
+    /// let b = InvertedIndex::build(data.into_iter().collect::<SparseDataset<f32>>(), config);
+    ///
+    /// let index = InvertedIndex::merge(a, b).unwrap();
+    ///
+    /// assert_eq!(index.len(), 3);
+    /// assert_eq!(index.dim(), 5);
+    /// assert_eq!(index.search(&[4], &[1.0], 1, 1, 0.0), vec![(1.0, 2)]);
+    /// ```
+    pub fn merge(a: Self, b: Self) -> Result<Self, MergeError> {
+        if a.config != b.config {
+            return Err(MergeError::DifferentConfigurations);
+        }
+        if a.rerank_dataset.is_some() != b.rerank_dataset.is_some() {
+            return Err(MergeError::RerankDatasetMismatch);
+        }
+        if a.passage_documents.is_some() != b.passage_documents.is_some() {
+            return Err(MergeError::PassageDocumentsMismatch);
+        }
+
+        // The lists of each component, with their postings as ids in the merged index.
+        let mut lists: Vec<_> = (0..a.dim().max(b.dim())).map(|_| Vec::new()).collect();
+        let lists_a = Self::take_posting_lists(a.posting_lists, &a.forward_index, 0);
+        let lists_b =
+            Self::take_posting_lists(b.posting_lists, &b.forward_index, a.forward_index.len());
+        for (component, list) in lists_a.into_iter().enumerate() {
+            lists[component].push(list);
+        }
+        for (component, list) in lists_b.into_iter().enumerate() {
+            lists[component].push(list);
+        }
+
+        let forward_index = match (a.forward_index, b.forward_index) {
+            (ForwardIndex::Plain(dataset_a), ForwardIndex::Plain(dataset_b)) => {
+                ForwardIndex::Plain(dataset_a.iter().chain(dataset_b.iter()).collect())
+            }
+            (ForwardIndex::Compressed(mut dataset_a), ForwardIndex::Compressed(dataset_b)) => {
+                dataset_a.append(&dataset_b);
+                ForwardIndex::Compressed(dataset_a)
+            }
+            _ => {
+                unreachable!("The representation of the forward index depends on the configuration")
+            }
+        };
+        let posting_lists = Self::reprune_posting_lists(&forward_index, lists, &a.config);
+
+        let rerank_dataset = match (a.rerank_dataset, b.rerank_dataset) {
+            (Some(dataset_a), Some(dataset_b)) => {
+                Some(dataset_a.iter().chain(dataset_b.iter()).collect())
+            }
+            _ => None,
+        };
+
+        let passage_documents = match (a.passage_documents, b.passage_documents) {
+            (Some(documents_a), Some(documents_b)) => {
+                let n_documents_a = documents_a.iter().max().map_or(0, |&doc_id| doc_id + 1);
+                let documents = documents_a
+                    .iter()
+                    .copied()
+                    .chain(documents_b.iter().map(|&doc_id| doc_id + n_documents_a));
+                Some(documents.collect())
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            forward_index,
+            posting_lists: posting_lists.into_boxed_slice(),
+            config: a.config,
+            rerank_dataset,
+            passage_documents,
+        })
+    }
+
+    /// Computes again the summaries of the blocks with the summarization strategy
+    /// `summarization`, which replaces the one of the configuration. The postings and their
+    /// division into blocks are kept, while blocks are reordered if the block order of the
+    /// configuration depends on the summaries.
+    ///
+    /// This is much faster than building the index again, e.g., to tune the summaries.
+    /// If the forward index is compressed, summaries are computed on its dequantized vectors,
+    /// which are decoded list by list.
+    ///
+    /// # Example
+    /// ```
+    /// use seismic::inverted_index::{Configuration, SummarizationStrategy};
+    /// use seismic::{InvertedIndex, SparseDataset};
+    ///
+    /// let data = vec![(vec![0, 2], vec![1.0, 2.0]), (vec![1, 3], vec![4.0, 5.0])];
+    /// let dataset: SparseDataset<f32> = data.into_iter().collect();
+    /// let index = InvertedIndex::build(dataset, Configuration::default());
+    ///
+    /// let summarization = SummarizationStrategy::EnergyPerserving { summary_energy: 0.9 };
+    /// let index = index.resummarize(summarization.clone());
+    ///
+    /// assert_eq!(index.config().summarization(), &summarization);
+    /// ```
+    #[must_use]
+    pub fn resummarize(mut self, summarization: SummarizationStrategy) -> Self {
+        self.config = self.config.summarization_strategy(summarization);
+
+        let (forward_index, config) = (&self.forward_index, &self.config);
+        match forward_index {
+            ForwardIndex::Plain(dataset) => Self::resummarize_posting_lists(
+                &mut self.posting_lists,
+                dataset,
+                forward_index,
+                config,
+            ),
+            ForwardIndex::Compressed(dataset) => Self::resummarize_posting_lists(
+                &mut self.posting_lists,
+                dataset,
+                forward_index,
+                config,
+            ),
+        }
+
+        self
+    }
+
+    fn resummarize_posting_lists<V>(
+        posting_lists: &mut [PostingList],
+        vectors: &V,
+        forward_index: &ForwardIndex<T>,
+        config: &Configuration,
+    ) where
+        V: ListVectors,
+    {
+        posting_lists.par_iter_mut().for_each(|list| {
+            let mut doc_ids = list.doc_ids(forward_index);
+            doc_ids.sort_unstable();
+            vectors.with_vectors(&doc_ids, |dataset, vector_doc_ids| {
+                list.resummarize(dataset, vector_doc_ids, forward_index, config)
+            })
+        })
+    }
+
+    /// Prunes the posting lists again with the pruning strategy `pruning`, which replaces the
+    /// one of the configuration. The lists whose postings change are clustered and summarized
+    /// again, the others are kept.
+    ///
+    /// This derives a smaller index from a larger one much faster than building it again.
+    /// Postings pruned while building the index cannot be recovered, so `pruning` must be of
+    /// the same kind as the current strategy and at least as aggressive, see
+    /// [`PruningStrategy::is_as_tight_as`]. For example, reducing `n_postings` of `FixedSize`
+    /// pruning gives the same postings as building the index from scratch.
+    ///
+    /// # Errors
+    /// Returns a [`RepruneError`] if `pruning` may keep postings that the current strategy
+    /// has pruned. The index is returned unchanged with the error.
+    ///
+    /// # Example
+    /// ```
+    /// use seismic::inverted_index::{Configuration, PruningStrategy};
+    /// use seismic::{InvertedIndex, SparseDataset};
+    ///
+    /// let data = vec![(vec![0, 2], vec![1.0, 2.0]), (vec![0, 3], vec![4.0, 5.0])];
+    /// let dataset: SparseDataset<f32> = data.into_iter().collect();
+    /// let index = InvertedIndex::build(dataset, Configuration::default());
+    ///
+    /// let index = index.reprune(PruningStrategy::FixedSize { n_postings: 1 }).unwrap();
+    ///
+    /// assert_eq!(index.stats().n_postings, 3);
+    /// assert!(index.reprune(PruningStrategy::FixedSize { n_postings: 2 }).is_err());
+    /// ```
+    pub fn reprune(mut self, pruning: PruningStrategy) -> Result<Self, RepruneError<T>> {
+        if !pruning.is_as_tight_as(&self.config.pruning) {
+            return Err(RepruneError {
+                index: Box::new(self),
+                pruning,
+            });
+        }
+        self.config = self.config.pruning_strategy(pruning);
+
+        let posting_lists = mem::take(&mut self.posting_lists);
+        let lists = Self::take_posting_lists(posting_lists, &self.forward_index, 0)
+            .into_iter()
+            .map(|list| vec![list])
+            .collect();
+        self.posting_lists = Self::reprune_posting_lists(&self.forward_index, lists, &self.config)
+            .into_boxed_slice();
+
+        Ok(self)
+    }
+
+    // Returns each posting list together with the ids of its documents, block by block,
+    // shifted by `first_doc_id`.
+    fn take_posting_lists(
+        posting_lists: Box<[PostingList]>,
+        forward_index: &ForwardIndex<T>,
+        first_doc_id: usize,
+    ) -> Vec<(PostingList, Vec<usize>)> {
+        posting_lists
+            .into_vec()
+            .into_par_iter()
+            .map(|list| {
+                let doc_ids = list.doc_ids(forward_index);
+                let doc_ids = doc_ids.into_iter().map(|doc_id| doc_id + first_doc_id);
+                (list, doc_ids.collect())
+            })
+            .collect()
+    }
+
+    // Combines the postings of the lists of each component and prunes them again. The lists are
+    // given with the ids of their documents in `forward_index`, block by block. A list is reused
+    // if it has exactly the pruned postings, otherwise the list of the component is built again.
+    // If the forward index is compressed, scores, blocks, and summaries are computed on its
+    // dequantized vectors, which are decoded list by list.
+    fn reprune_posting_lists(
+        forward_index: &ForwardIndex<T>,
+        lists: Vec<Vec<(PostingList, Vec<usize>)>>,
+        config: &Configuration,
+    ) -> Vec<PostingList> {
+        match forward_index {
+            ForwardIndex::Plain(dataset) => {
+                Self::reprune_posting_lists_with_vectors(dataset, forward_index, lists, config)
+            }
+            ForwardIndex::Compressed(dataset) => {
+                Self::reprune_posting_lists_with_vectors(dataset, forward_index, lists, config)
+            }
+        }
+    }
+
+    fn reprune_posting_lists_with_vectors<V>(
+        vectors: &V,
+        forward_index: &ForwardIndex<T>,
+        lists: Vec<Vec<(PostingList, Vec<usize>)>>,
+        config: &Configuration,
+    ) -> Vec<PostingList>
+    where
+        V: ListVectors,
+    {
+        // Document-centric pruning keeps only the postings of the largest components of each document.
+        let document_components: Option<Vec<Vec<u16>>> = match config.pruning {
+            PruningStrategy::DocumentCentric { n_components } => {
+                let doc_ids: Vec<_> = (0..forward_index.len()).collect();
+                Some(
+                    doc_ids
+                        .par_chunks(DECODED_CHUNK_SIZE)
+                        .flat_map_iter(|doc_ids| {
+                            vectors.with_vectors(doc_ids, |dataset, vector_doc_ids| {
+                                doc_ids
+                                    .iter()
+                                    .map(|&doc_id| {
+                                        let vector_id = vector_id(vector_doc_ids, doc_id);
+                                        let (components, values) = dataset.get(vector_id);
+                                        let postings = InvertedIndex::<V::Value>::document_postings(
+                                            components,
+                                            values,
+                                            n_components,
+                                        );
+                                        postings.map(|(c, _)| c).sorted_unstable().collect()
+                                    })
+                                    .collect::<Vec<_>>()
+                            })
+                        })
+                        .collect(),
+                )
+            }
+            _ => None,
+        };
+
+        let mut inverted_pairs: Vec<Vec<(V::Value, usize)>> = lists
+            .par_iter()
+            .enumerate()
+            .map(|(component, lists)| {
+                let component = component as u16;
+                let mut doc_ids: Vec<_> = lists
+                    .iter()
+                    .flat_map(|(_, doc_ids)| doc_ids)
+                    .copied()
+                    .filter(|&doc_id| {
+                        document_components
+                            .as_ref()
+                            .is_none_or(|document_components| {
+                                document_components[doc_id]
+                                    .binary_search(&component)
+                                    .is_ok()
+                            })
+                    })
+                    .collect();
+                doc_ids.sort_unstable();
+
+                vectors.with_vectors(&doc_ids, |dataset, vector_doc_ids| {
+                    doc_ids
+                        .iter()
+                        .map(|&doc_id| {
+                            let (components, values) =
+                                dataset.get(vector_id(vector_doc_ids, doc_id));
+                            let i = components.binary_search(&component).unwrap();
+                            (values[i], doc_id)
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+
+        InvertedIndex::<V::Value>::prune(&mut inverted_pairs, &config.pruning);
+
+        let mut posting_lists: Vec<_> = inverted_pairs
+            .into_par_iter()
+            .zip(lists)
+            .map(|(postings, lists)| {
+                let mut pruned_doc_ids: Vec<_> =
+                    postings.iter().map(|&(_, doc_id)| doc_id).collect();
+                pruned_doc_ids.sort_unstable();
+                let has_pruned_postings = |doc_ids: &[usize]| {
+                    let mut doc_ids = doc_ids.to_vec();
+                    doc_ids.sort_unstable();
+                    doc_ids == pruned_doc_ids
+                };
+
+                match lists
+                    .into_iter()
+                    .find(|(_, doc_ids)| has_pruned_postings(doc_ids))
+                {
+                    Some((list, mut doc_ids)) => {
+                        list.remap(&mut doc_ids, forward_index, &config.postings)
+                    }
+                    None => vectors.with_vectors(&pruned_doc_ids, |dataset, vector_doc_ids| {
+                        let postings: Vec<_> = postings
+                            .iter()
+                            .map(|&(score, doc_id)| (score, vector_id(vector_doc_ids, doc_id)))
+                            .collect();
+                        PostingList::build_with_doc_ids(
+                            dataset,
+                            vector_doc_ids,
+                            forward_index,
+                            &postings,
+                            config,
+                        )
+                    }),
+                }
+            })
+            .collect();
+        Self::set_pruned_values(&mut posting_lists, forward_index);
+
+        posting_lists
+    }
+
+    // Sets the value pruned away from each posting list, i.e., the largest value of its component
+    // among the vectors which are not in the list. These values bound the contribution of the
+    // visited lists to the vectors not found in them, see `collect_candidates`.
+    fn set_pruned_values(posting_lists: &mut [PostingList], forward_index: &ForwardIndex<T>) {
+        let pruned_values = match forward_index {
+            ForwardIndex::Plain(dataset) => {
+                Self::pruned_values(dataset, forward_index, posting_lists)
+            }
+            ForwardIndex::Compressed(dataset) => {
+                Self::pruned_values(dataset, forward_index, posting_lists)
+            }
+        };
+
+        posting_lists
+            .iter_mut()
+            .zip(pruned_values)
+            .for_each(|(list, pruned_value)| list.pruned_value = pruned_value);
+    }
+
+    fn pruned_values<V>(
+        vectors: &V,
+        forward_index: &ForwardIndex<T>,
+        posting_lists: &[PostingList],
+    ) -> Vec<f32>
+    where
+        V: ListVectors,
+    {
+        let list_doc_ids: Vec<_> = posting_lists
+            .par_iter()
+            .map(|list| {
+                let mut doc_ids = list.doc_ids(forward_index);
+                doc_ids.sort_unstable();
+                doc_ids
+            })
+            .collect();
+
+        let doc_ids: Vec<_> = (0..forward_index.len()).collect();
+        doc_ids
+            .par_chunks(DECODED_CHUNK_SIZE)
+            .map(|doc_ids| {
+                vectors.with_vectors(doc_ids, |dataset, vector_doc_ids| {
+                    let mut pruned_values = vec![0.0_f32; posting_lists.len()];
+                    for &doc_id in doc_ids {
+                        let (components, values) = dataset.get(vector_id(vector_doc_ids, doc_id));
+                        for (&c, value) in components.iter().zip(values) {
+                            let c = c as usize;
+                            if c < list_doc_ids.len()
+                                && list_doc_ids[c].binary_search(&doc_id).is_err()
+                            {
+                                pruned_values[c] = pruned_values[c].max(value.to_f32().unwrap());
+                            }
+                        }
+                    }
+                    pruned_values
+                })
+            })
+            .reduce(
+                || vec![0.0; posting_lists.len()],
+                |mut a, b| {
+                    a.iter_mut().zip(b).for_each(|(a, b)| *a = a.max(b));
+                    a
+                },
+            )
+    }
+
+    // Prunes the posting lists to at most `tot_postings` postings overall according to the kind
+    // of `pruning`. Returns the pruning strategy that has been actually applied.
+    fn budget_pruning(
+        inverted_pairs: &mut [Vec<(T, usize)>],
+        pruning: &PruningStrategy,
+        tot_postings: usize,
+    ) -> PruningStrategy {
+        match *pruning {
+            PruningStrategy::GlobalThreshold { max_fraction, .. } => {
+                let pruning = PruningStrategy::GlobalThreshold {
+                    n_postings: tot_postings / inverted_pairs.len().max(1),
+                    max_fraction,
+                };
+                Self::prune(inverted_pairs, &pruning);
+                pruning
+            }
+            _ => {
+                let n_postings = |inverted_pairs: &[Vec<(T, usize)>]| -> usize {
+                    inverted_pairs.iter().map(|list| list.len()).sum()
+                };
+                let n_postings_before = n_postings(inverted_pairs);
+                let uniform_weights = vec![1.0; inverted_pairs.len()];
+                Self::weighted_pruning(inverted_pairs, &uniform_weights, tot_postings, usize::MAX);
+
+                match pruning {
+                    PruningStrategy::FixedSize { .. } => PruningStrategy::FixedSize {
+                        n_postings: inverted_pairs
+                            .iter()
+                            .map(|list| list.len())
+                            .max()
+                            .unwrap_or(0),
+                    },
+                    // The strategy is applied as it is only if no list has been shortened,
+                    // otherwise the lists are described by their average and largest length.
+                    _ if n_postings(inverted_pairs) == n_postings_before => pruning.clone(),
+                    _ => Self::weighted_pruning_strategy(inverted_pairs),
+                }
+            }
+        }
+    }
+
+    fn build_with_list_weights(
+        dataset: SparseDataset<T>,
+        config: Configuration,
+        list_weights: Option<Vec<f32>>,
+        observer: &dyn BuildObserver,
+    ) -> Result<Self, BuildCancelled> {
+        let mut inverted_pairs = Self::distribute_postings(&dataset, &config.pruning, observer)?;
+
+        // Apply the selected pruning strategy
+        let list_blockings = observe_phase(observer, BuildPhase::PruningPostings, None, || {
+            match list_weights {
+                // With list weights, the pruning strategy only determines the overall number of
+                // postings and, with `GlobalThreshold`, the length of the longest list.
+                Some(list_weights) => {
+                    let tot_postings = Self::pruned_lengths(&mut inverted_pairs, &config.pruning)
+                        .into_iter()
+                        .sum();
+                    let max_len = match config.pruning {
+                        PruningStrategy::GlobalThreshold {
+                            n_postings,
+                            max_fraction,
+                        } => (n_postings as f32 * max_fraction) as usize,
+                        _ => usize::MAX,
+                    };
+                    Self::weighted_pruning(
+                        &mut inverted_pairs,
+                        &list_weights,
+                        tot_postings,
+                        max_len,
+                    );
+
+                    Ok(Some(Self::weighted_blocking(
+                        &inverted_pairs,
+                        &list_weights,
+                        &config.blocking,
+                    )))
+                }
+                None => {
+                    Self::prune(&mut inverted_pairs, &config.pruning);
+                    Ok(None)
+                }
+            }
+        })?;
+
+        // The lists pruned by weight are described by their average and largest length.
+        let config = match list_blockings {
+            Some(_) => {
+                let pruning = Self::weighted_pruning_strategy(&inverted_pairs);
+                config.pruning_strategy(pruning)
+            }
+            None => config,
+        };
+
+        let (forward_index, original_dataset) =
+            Self::build_forward_index(dataset, &config.forward_index, observer)?;
+        let dataset = original_dataset
+            .as_ref()
+            .unwrap_or_else(|| forward_index.as_plain().unwrap());
+
+        let mut posting_lists = Self::build_posting_lists(
+            dataset,
+            &forward_index,
+            &inverted_pairs,
+            &config,
+            list_blockings.as_deref(),
+            observer,
+        )?;
+        Self::set_pruned_values(&mut posting_lists, &forward_index);
+
+        Ok(Self {
+            forward_index,
+            posting_lists: posting_lists.into_boxed_slice(),
+            config,
+            rerank_dataset: None,
+            passage_documents: None,
+        })
+    }
+
+    // Distributes pairs (score, doc_id) to corresponding components.
+    // We use pairs because later each posting list will be sorted by score
+    // by the pruning strategy.
+    //
+    // The postings are transposed with a counting sort. Each thread takes a range of documents:
+    // a first pass counts its postings in each list, so that every list is allocated with its
+    // exact length and split into the parts of the threads. A second pass fills the parts.
+    // The pairs of each list are sorted by doc_id.
+    fn distribute_postings(
+        dataset: &SparseDataset<T>,
+        pruning: &PruningStrategy,
+        observer: &dyn BuildObserver,
+    ) -> Result<Vec<Vec<(T, usize)>>, BuildCancelled> {
+        let phase = BuildPhase::DistributingPostings;
+        observe_phase(observer, phase, Some(dataset.len()), || {
+            // Document-centric pruning only distributes the top components of each document.
+            let max_doc_components = match *pruning {
+                PruningStrategy::DocumentCentric { n_components } => n_components,
+                _ => usize::MAX,
+            };
+            let doc_postings = |doc_id: usize| {
+                let (components, values) = dataset.get(doc_id);
+                Self::document_postings(components, values, max_doc_components)
+            };
+
+            let chunk_size = dataset.len().div_ceil(rayon::current_num_threads()).max(1);
+            let chunks: Vec<_> = (0..dataset.len())
+                .step_by(chunk_size)
+                .map(|start| start..(start + chunk_size).min(dataset.len()))
+                .collect();
+
+            // With document-centric pruning, the postings selected for the documents of a chunk
+            // are kept for the second pass, so that the top components are selected only once.
+            // A document has `min(len, n_components)` of them.
+            let is_document_centric = max_doc_components < usize::MAX;
+            let (chunk_counts, chunk_selected): (Vec<_>, Vec<_>) = chunks
+                .par_iter()
+                .map(|chunk| {
+                    let mut counts = vec![0; dataset.dim()];
+                    let mut selected = is_document_centric.then(Vec::new);
+                    for doc_id in chunk.clone() {
+                        for (c, score) in doc_postings(doc_id) {
+                            counts[c as usize] += 1;
+                            if let Some(selected) = selected.as_mut() {
+                                selected.push((c, score));
+                            }
+                        }
+                    }
+                    (counts, selected)
+                })
+                .unzip();
+
+            let mut inverted_pairs: Vec<Vec<(T, usize)>> = (0..dataset.dim())
+                .into_par_iter()
+                .map(|c| {
+                    let len = chunk_counts.iter().map(|counts| counts[c]).sum();
+                    vec![(T::zero(), 0); len]
+                })
+                .collect();
+
+            let mut chunk_parts: Vec<Vec<_>> = chunks
+                .iter()
+                .map(|_| Vec::with_capacity(dataset.dim()))
+                .collect();
+            for (c, mut posting_list) in inverted_pairs
+                .iter_mut()
+                .map(|posting_list| posting_list.as_mut_slice())
+                .enumerate()
+            {
+                for (parts, counts) in chunk_parts.iter_mut().zip(&chunk_counts) {
+                    let (part, rest) = mem::take(&mut posting_list).split_at_mut(counts[c]);
+                    parts.push(part.iter_mut());
+                    posting_list = rest;
+                }
+            }
+            drop(chunk_counts);
+
+            chunks
+                .into_par_iter()
+                .zip(chunk_selected)
+                .zip(chunk_parts)
+                .try_for_each(|((chunk, selected), mut parts)| {
+                    let chunk_len = chunk.len();
+                    let mut selected = selected.map(|selected| selected.into_iter());
+                    for (i, doc_id) in chunk.enumerate() {
+                        let postings = match selected.as_mut() {
+                            Some(selected) => {
+                                let n = dataset.vector_len(doc_id).min(max_doc_components);
+                                Either::Left(selected.take(n))
+                            }
+                            None => Either::Right(doc_postings(doc_id)),
+                        };
+                        for (c, score) in postings {
+                            *parts[c as usize].next().unwrap() = (score, doc_id);
+                        }
+
+                        if (i + 1) % PROGRESS_STEP == 0 {
+                            observer.progress(phase, PROGRESS_STEP);
+                            check_cancelled(observer)?;
+                        }
+                    }
+                    observer.progress(phase, chunk_len % PROGRESS_STEP);
+                    Ok(())
+                })?;
+
+            Ok(inverted_pairs)
+        })
+    }
+
+    // Returns the pairs (component, score) of the document to distribute to the posting lists,
+    // i.e., its `max_doc_components` largest components, or all of them if they are fewer.
+    fn document_postings<'a>(
+        components: &'a [u16],
+        values: &'a [T],
+        max_doc_components: usize,
+    ) -> impl Iterator<Item = (u16, T)> + 'a {
+        if components.len() <= max_doc_components {
+            Either::Left(components.iter().copied().zip(values.iter().copied()))
+        } else {
+            let mut pairs: Vec<_> = components
+                .iter()
+                .copied()
+                .zip(values.iter().copied())
+                .collect();
+            pairs.select_nth_unstable_by(max_doc_components, |a, b| b.1.partial_cmp(&a.1).unwrap());
+            pairs.truncate(max_doc_components);
+            Either::Right(pairs.into_iter())
+        }
+    }
+
+    // Applies the selected pruning strategy
+    fn prune(inverted_pairs: &mut [Vec<(T, usize)>], pruning: &PruningStrategy) {
+        let n_kept = Self::pruned_lengths(inverted_pairs, pruning);
+
+        inverted_pairs
+            .par_iter_mut()
+            .zip(n_kept)
+            .for_each(|(posting_list, n)| {
+                posting_list.truncate(n);
+
+                posting_list.shrink_to_fit();
+            });
+    }
+
+    // Sorts each posting list by decreasing score and returns the number of postings
+    // that the pruning strategy keeps in each list, without removing them.
+    fn pruned_lengths(
+        inverted_pairs: &mut [Vec<(T, usize)>],
+        pruning: &PruningStrategy,
+    ) -> Vec<usize> {
+        inverted_pairs.par_iter_mut().for_each(|posting_list| {
+            posting_list.sort_unstable_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+        });
+
+        match *pruning {
+            PruningStrategy::FixedSize { n_postings } => inverted_pairs
+                .iter()
+                .map(|posting_list| posting_list.len().min(n_postings))
+                .collect(),
+
+            PruningStrategy::GlobalThreshold {
+                n_postings,
+                max_fraction,
+            } => {
+                // cuts too long lists
+                let max_len = (n_postings as f32 * max_fraction) as usize;
+                Self::global_threshold_lengths(inverted_pairs, n_postings)
+                    .into_iter()
+                    .map(|n| n.min(max_len))
+                    .collect()
+            }
+
+            PruningStrategy::EnergyPreserving { fraction } => inverted_pairs
+                .par_iter()
+                .map(|posting_list| Self::energy_preserving_length(posting_list, fraction))
+                .collect(),
+
+            PruningStrategy::Threshold { threshold } => inverted_pairs
+                .par_iter()
+                .map(|posting_list| {
+                    posting_list.partition_point(|(score, _)| score.to_f32().unwrap() >= threshold)
+                })
+                .collect(),
+
+            // Postings are already pruned, the lists are only sorted by score.
+            PruningStrategy::DocumentCentric { .. } => inverted_pairs
+                .iter()
+                .map(|posting_list| posting_list.len())
+                .collect(),
+        }
+    }
+
+    // The plain dataset is still needed to cluster and summarize the posting lists
+    // even if the forward index is compressed. In this case, it is returned as well.
+    fn build_forward_index(
+        dataset: SparseDataset<T>,
+        strategy: &ForwardIndexStrategy,
+        observer: &dyn BuildObserver,
+    ) -> Result<(ForwardIndex<T>, Option<SparseDataset<T>>), BuildCancelled> {
+        match *strategy {
+            ForwardIndexStrategy::Uncompressed => Ok((ForwardIndex::Plain(dataset), None)),
+            ForwardIndexStrategy::Compressed { quantization } => {
+                let compressed =
+                    observe_phase(observer, BuildPhase::CompressingForwardIndex, None, || {
+                        Ok(CompressedSparseDataset::from_dataset(
+                            &dataset,
+                            quantization,
+                        ))
+                    })?;
+
+                Ok((ForwardIndex::Compressed(compressed), Some(dataset)))
+            }
+        }
+    }
+
+    // Builds summaries and blocks for each posting list. If given, `list_blockings` replaces
+    // the blocking strategy of the configuration for each posting list.
+    fn build_posting_lists(
+        dataset: &SparseDataset<T>,
+        forward_index: &ForwardIndex<T>,
+        inverted_pairs: &[Vec<(T, usize)>],
+        config: &Configuration,
+        list_blockings: Option<&[BlockingStrategy]>,
+        observer: &dyn BuildObserver,
+    ) -> Result<Vec<PostingList>, BuildCancelled> {
+        let phase = BuildPhase::BuildingPostingLists;
+        observe_phase(observer, phase, Some(inverted_pairs.len()), || {
+            inverted_pairs
+                .par_iter()
+                .enumerate()
+                .map(|(component_id, posting_list)| {
+                    check_cancelled(observer)?;
+                    let list = match list_blockings {
+                        Some(list_blockings) => {
+                            let list_config = config
+                                .clone()
+                                .blocking_strategy(list_blockings[component_id].clone());
+                            PostingList::build(dataset, forward_index, posting_list, &list_config)
+                        }
+                        None => PostingList::build(dataset, forward_index, posting_list, config),
+                    };
+                    observer.progress(phase, 1);
+                    Ok(list)
+                })
+                .collect()
+        })
+    }
+
+    // Builds again the posting lists which have been shortened since `previous_lists` were built,
+    // and reuses the others. Pruning only removes postings, so a list has the same postings if
+    // it has the same length.
+    fn rebuild_shortened_lists(
+        dataset: &SparseDataset<T>,
+        forward_index: &ForwardIndex<T>,
+        inverted_pairs: &[Vec<(T, usize)>],
+        config: &Configuration,
+        previous_lists: Vec<PostingList>,
+        observer: &dyn BuildObserver,
+    ) -> Result<Vec<PostingList>, BuildCancelled> {
+        let phase = BuildPhase::BuildingPostingLists;
+        observe_phase(observer, phase, Some(inverted_pairs.len()), || {
+            inverted_pairs
+                .par_iter()
+                .zip(previous_lists)
+                .map(|(posting_list, previous_list)| {
+                    check_cancelled(observer)?;
+                    let list = if previous_list.len() == posting_list.len() {
+                        previous_list
+                    } else {
+                        PostingList::build(dataset, forward_index, posting_list, config)
+                    };
+                    observer.progress(phase, 1);
+                    Ok(list)
+                })
+                .collect()
+        })
+    }
+
+    // Implementation of the pruning strategy that selects the top postings of a posting list,
+    // sorted by decreasing score, until their scores sum up to `fraction` of the scores of the whole list
+    fn energy_preserving_length(posting_list: &[(T, usize)], fraction: f32) -> usize {
+        let total_energy: f32 = posting_list
+            .iter()
+            .map(|(score, _)| score.to_f32().unwrap())
+            .sum();
+
+        let mut acc = 0.0;
+        for (i, (score, _)) in posting_list.iter().enumerate() {
+            if acc >= fraction * total_energy {
+                return i;
+            }
+            acc += score.to_f32().unwrap();
+        }
+
+        posting_list.len()
+    }
+
+    // Keeps `tot_postings` postings overall, giving to each posting list a number of postings
+    // proportional to its weight, unless the list is shorter, and at most `max_len`
+    fn weighted_pruning(
+        inverted_pairs: &mut [Vec<(T, usize)>],
+        list_weights: &[f32],
+        tot_postings: usize,
+        max_len: usize,
+    ) {
+        let n_postings = |scale: f64| -> Vec<usize> {
+            inverted_pairs
+                .iter()
+                .zip(list_weights)
+                .map(|(list, &weight)| {
+                    list.len()
+                        .min(max_len)
+                        .min((scale * weight as f64) as usize)
+                })
+                .collect()
+        };
+
+        // Binary search of the largest scale whose lists fit into `tot_postings`.
+        let (mut low, mut high) = (0.0_f64, 1.0_f64);
+        while high < 1e15 && n_postings(high).iter().sum::<usize>() < tot_postings {
+            high *= 2.0;
+        }
+        for _ in 0..64 {
+            let mid = (low + high) / 2.0;
+            if n_postings(mid).iter().sum::<usize>() <= tot_postings {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        let n_postings = n_postings(low);
+        inverted_pairs
+            .par_iter_mut()
+            .zip(n_postings)
+            .for_each(|(posting_list, n)| {
+                posting_list.sort_unstable_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+
+                posting_list.truncate(n);
+
+                posting_list.shrink_to_fit();
+            });
+    }
+
+    // Returns the `GlobalThreshold` pruning strategy with the average number of postings of
+    // the lists, and a `max_fraction` which bounds the length of the longest one.
+    fn weighted_pruning_strategy(inverted_pairs: &[Vec<(T, usize)>]) -> PruningStrategy {
+        let tot_postings: usize = inverted_pairs.iter().map(|list| list.len()).sum();
+        let max_len = inverted_pairs
+            .iter()
+            .map(|list| list.len())
+            .max()
+            .unwrap_or(0);
+        let n_postings = tot_postings.div_ceil(inverted_pairs.len().max(1)).max(1);
+
+        PruningStrategy::GlobalThreshold {
+            n_postings,
+            max_fraction: max_len as f32 / n_postings as f32,
+        }
+    }
+
+    // Scales the granularity of the blocks of each posting list by its weight, normalized so that
+    // the overall number of blocks stays roughly the same
+    fn weighted_blocking(
+        inverted_pairs: &[Vec<(T, usize)>],
+        list_weights: &[f32],
+        blocking: &BlockingStrategy,
+    ) -> Vec<BlockingStrategy> {
+        let tot_postings: f32 = inverted_pairs.iter().map(|list| list.len() as f32).sum();
+        let tot_weighted_postings: f32 = inverted_pairs
+            .iter()
+            .zip(list_weights)
+            .map(|(list, &weight)| list.len() as f32 * weight)
+            .sum();
+        let normalization = if tot_weighted_postings > 0.0 {
+            tot_postings / tot_weighted_postings
+        } else {
+            1.0
+        };
+
+        list_weights
+            .iter()
+            .map(|&weight| {
+                let granularity = weight * normalization;
+                match blocking.clone() {
+                    BlockingStrategy::FixedSize { block_size } => BlockingStrategy::FixedSize {
+                        block_size: ((block_size as f32 / granularity).round() as usize).max(1),
+                    },
+                    BlockingStrategy::RandomKmeans {
+                        centroid_fraction,
+                        truncated_kmeans_training,
+                        truncation_size,
+                        min_cluster_size,
+                    } => BlockingStrategy::RandomKmeans {
+                        centroid_fraction: (centroid_fraction * granularity).min(1.0),
+                        truncated_kmeans_training,
+                        truncation_size,
+                        min_cluster_size,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    // Implementation of the pruning strategy that selects a threshold such that survives on average `n_postings` for each posting list.
+    // Returns the number of postings above the threshold in each list, sorted by decreasing score.
+    //
+    // As the lists are sorted, the postings above a threshold are a prefix of each list and can be
+    // counted with a binary search. The threshold is found by bisection on `score_key`.
+    // Postings whose score equals the threshold are kept in order of list.
+    fn global_threshold_lengths(
+        inverted_pairs: &[Vec<(T, usize)>],
+        n_postings: usize,
+    ) -> Vec<usize> {
+        let n_all: usize = inverted_pairs.iter().map(|list| list.len()).sum();
+        // overall number of postings to select
+        let tot_postings = (inverted_pairs.len() * n_postings).min(n_all.saturating_sub(1));
+
+        let n_at_least = |key: u64| -> usize {
+            inverted_pairs
+                .par_iter()
+                .map(|posting_list| {
+                    posting_list.partition_point(|&(score, _)| Self::score_key(score) >= key)
+                })
+                .sum()
+        };
+
+        // Binary search of the largest key with at least `tot_postings` postings.
+        let (mut low, mut high) = (0, u64::MAX);
+        while low < high {
+            let mid = low + (high - low).div_ceil(2);
+            if n_at_least(mid) >= tot_postings {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        let threshold = low;
+        let n_above = low.checked_add(1).map_or(0, n_at_least);
+
+        let mut n_ties = tot_postings.saturating_sub(n_above);
+        inverted_pairs
+            .iter()
+            .map(|posting_list| {
+                let above =
+                    posting_list.partition_point(|&(score, _)| Self::score_key(score) > threshold);
+                let ties = posting_list[above..]
+                    .partition_point(|&(score, _)| Self::score_key(score) == threshold)
+                    .min(n_ties);
+                n_ties -= ties;
+                above + ties
+            })
+            .collect()
+    }
+
+    // Maps a score to a key with the same order, i.e., `a < b` if and only if
+    // `score_key(a) < score_key(b)`. Adding `0.0` turns `-0.0` into `0.0`.
+    fn score_key(score: T) -> u64 {
+        let bits = (score.to_f64().unwrap() + 0.0).to_bits();
+        if bits >> 63 == 1 {
+            !bits
+        } else {
+            bits | 1 << 63
+        }
+    }
+
+    /// Returns the id of the largest component, i.e., the dimensionality of the vectors in the dataset.
+    #[must_use]
+    pub fn dim(&self) -> usize {
+        self.forward_index.dim()
+    }
+
+    /// Returns the number of non-zero components in the dataset.
+    #[must_use]
+    pub fn nnz(&self) -> usize {
+        self.forward_index.nnz()
+    }
+
+    /// Returns the number of vectors in the dataset
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.forward_index.len()
+    }
+
+    /// Checks if the dataset is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.forward_index.len() == 0
+    }
+
+    /// Computes statistics on the posting lists, the blocks, and the summaries of the index,
+    /// together with its space usage broken down by component.
+    #[must_use]
+    pub fn stats(&self) -> IndexStats {
+        let non_empty_lists = || self.posting_lists.iter().filter(|list| list.len() > 0);
+
+        let block_sizes = self
+            .posting_lists
+            .iter()
+            .flat_map(|list| list.block_offsets.windows(2).map(|w| w[1] - w[0]));
+        let summary_lengths = self
+            .posting_lists
+            .iter()
+            .flat_map(|list| list.summaries.summary_lengths());
+        let summary_ranges = self
+            .posting_lists
+            .iter()
+            .flat_map(|list| list.summaries.value_ranges());
+
+        let mut space_usage = SpaceBreakdown {
+            forward_index: self.forward_index.space_usage_byte(),
+            rerank_dataset: self
+                .rerank_dataset
+                .as_ref()
+                .map_or(0, |dataset| dataset.space_usage_byte()),
+            passage_documents: self
+                .passage_documents
+                .as_ref()
+                .map_or(0, |passage_documents| passage_documents.space_usage_byte()),
+            ..SpaceBreakdown::default()
+        };
+        for list in self.posting_lists.iter() {
+            space_usage.postings += list.postings.space_usage_byte();
+            space_usage.block_offsets += list.block_offsets.space_usage_byte();
+            space_usage.summaries += list.summaries.space_usage_byte();
+            space_usage.max_values +=
+                list.max_value.space_usage_byte() + list.pruned_value.space_usage_byte();
+        }
+        space_usage.total = self.space_usage_byte();
+
+        IndexStats {
+            n_documents: self.len(),
+            dim: self.dim(),
+            nnz: self.nnz(),
+            n_postings: self.posting_lists.iter().map(|list| list.len()).sum(),
+            n_posting_lists: self.posting_lists.len(),
+            n_empty_posting_lists: self.posting_lists.len() - non_empty_lists().count(),
+            posting_list_lengths: Histogram::new(non_empty_lists().map(|list| list.len())),
+            n_blocks: self.posting_lists.iter().map(|list| list.n_blocks()).sum(),
+            blocks_per_list: Histogram::new(non_empty_lists().map(|list| list.n_blocks())),
+            block_sizes: Histogram::new(block_sizes),
+            summary_lengths: Histogram::new(summary_lengths),
+            summary_quantization: QuantizationStats::new(summary_ranges),
+            space_usage,
+            configuration: self.config.clone(),
+        }
+    }
+
+    /// Checks the invariants of the index and returns all the violations found, if any.
+    ///
+    /// The vectors of the forward index must have sorted components smaller than the
+    /// dimensionality, and each posting list must have increasing block offsets ending at its
+    /// number of postings, a summary for each block, and postings referring to vectors of the
+    /// forward index with their exact length. Postings are checked only if the forward index is
+    /// valid.
+    ///
+    /// An index built by this crate is always valid; a violation means that the index has been
+    /// corrupted, e.g., while being stored or copied.
+    ///
+    /// # Example
+    /// ```
+    /// use seismic::{InvertedIndex, SparseDataset};
+    /// use seismic::inverted_index::Configuration;
+    ///
+    /// let data = vec![(vec![0, 2], vec![1.0, 2.0]), (vec![1, 3], vec![4.0, 5.0])];
+    /// let dataset: SparseDataset<f32> = data.into_iter().collect();
+    /// let index = InvertedIndex::build(dataset, Configuration::default());
+    ///
+    /// assert_eq!(index.validate(), Ok(()));
+    /// ```
+    pub fn validate(&self) -> Result<(), Vec<Violation>> {
+        let mut violations = Vec::new();
+
+        let dim = self.dim();
+        self.forward_index.check_vectors(dim, &mut violations);
+        let check_postings = violations.is_empty();
+
+        if self.posting_lists.len() != dim {
+            violations.push(Violation::PostingListCount {
+                n_posting_lists: self.posting_lists.len(),
+                dim,
+            });
+        }
+
+        for (list, posting_list) in self.posting_lists.iter().enumerate() {
+            posting_list.check(
+                list,
+                &self.forward_index,
+                check_postings,
+                dim,
+                &mut violations,
+            );
+        }
+
+        if let Some(rerank_dataset) = &self.rerank_dataset {
+            if rerank_dataset.len() != self.len() {
+                violations.push(Violation::RerankDatasetLength {
+                    len: rerank_dataset.len(),
+                    n_documents: self.len(),
+                });
+            }
+
+            let mut rerank_violations = Vec::new();
+            rerank_dataset.check_vectors(dim, &mut rerank_violations);
+            violations.extend(
+                rerank_violations
+                    .into_iter()
+                    .map(|violation| Violation::RerankDataset(Box::new(violation))),
+            );
+        }
+
+        if let Some(passage_documents) = &self.passage_documents {
+            if passage_documents.len() != self.len() {
+                violations.push(Violation::PassageDocumentsLength {
+                    len: passage_documents.len(),
+                    n_documents: self.len(),
+                });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+// The forward index stores the vectors of the dataset, either as they are or compressed.
+// Postings refer to vectors by their offset and length within the forward index, so
+// the meaning of an offset depends on the representation.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+enum ForwardIndex<T>
+where
+    T: DataType,
+{
+    Plain(SparseDataset<T>),
+    Compressed(CompressedSparseDataset),
+}
+
+impl<T> Default for ForwardIndex<T>
+where
+    T: DataType + Default,
+{
+    fn default() -> Self {
+        Self::Plain(SparseDataset::default())
+    }
+}
+
+impl<T> SpaceUsage for ForwardIndex<T>
+where
+    T: DataType,
+{
+    fn space_usage_byte(&self) -> usize {
+        match self {
+            Self::Plain(dataset) => dataset.space_usage_byte(),
+            Self::Compressed(dataset) => dataset.space_usage_byte(),
+        }
+    }
+}
+
+impl<T> ForwardIndex<T>
+where
+    T: DataType,
+{
+    #[inline]
+    fn as_plain(&self) -> Option<&SparseDataset<T>> {
+        match self {
+            Self::Plain(dataset) => Some(dataset),
+            Self::Compressed(_) => None,
+        }
+    }
+
+    #[inline]
+    fn dot_product_with_offset(
+        &self,
+        query: &[f32],
+        query_term_ids: &[u16],
+        query_values: &[f32],
+        offset: usize,
+        len: usize,
+    ) -> f32 {
+        match self {
+            Self::Plain(dataset) => {
+                let (v_components, v_values) = dataset.get_with_offset(offset, len);
+                //let distance = dot_product_dense_sparse(query, v_components, v_values);
+                if query_term_ids.len() < THRESHOLD_BINARY_SEARCH {
+                    //dot_product_with_binary_search(
+                    dot_product_with_merge(query_term_ids, query_values, v_components, v_values)
+                } else {
+                    dot_product_dense_sparse(query, v_components, v_values)
+                }
+            }
+            Self::Compressed(dataset) => dataset.dot_product_dense_with_offset(query, offset),
+        }
+    }
+
+    #[inline]
+    fn prefetch_vec_with_offset(&self, offset: usize, len: usize) {
+        match self {
+            Self::Plain(dataset) => dataset.prefetch_vec_with_offset(offset, len),
+            Self::Compressed(dataset) => dataset.prefetch_vec_with_offset(offset, len),
+        }
+    }
+
+    #[inline]
+    fn vector_offset(&self, id: usize) -> usize {
+        match self {
+            Self::Plain(dataset) => dataset.vector_offset(id),
+            Self::Compressed(dataset) => dataset.vector_offset(id),
+        }
+    }
+
+    #[inline]
+    fn vector_len(&self, id: usize) -> usize {
+        match self {
+            Self::Plain(dataset) => dataset.vector_len(id),
+            Self::Compressed(dataset) => dataset.vector_len(id),
+        }
+    }
+
+    #[inline]
+    fn offset_to_id(&self, offset: usize) -> usize {
+        match self {
+            Self::Plain(dataset) => dataset.offset_to_id(offset),
+            Self::Compressed(dataset) => dataset.offset_to_id(offset),
+        }
+    }
+
+    #[inline]
+    fn checked_offset_to_id(&self, offset: usize) -> Option<usize> {
+        match self {
+            Self::Plain(dataset) => dataset.checked_offset_to_id(offset),
+            Self::Compressed(dataset) => dataset.checked_offset_to_id(offset),
+        }
+    }
+
+    fn check_vectors(&self, dim: usize, violations: &mut Vec<Violation>) {
+        match self {
+            Self::Plain(dataset) => dataset.check_vectors(dim, violations),
+            Self::Compressed(dataset) => dataset.check_vectors(dim, violations),
+        }
+    }
+
+    fn dim(&self) -> usize {
+        match self {
+            Self::Plain(dataset) => dataset.dim(),
+            Self::Compressed(dataset) => dataset.dim(),
+        }
+    }
+
+    fn nnz(&self) -> usize {
+        match self {
+            Self::Plain(dataset) => dataset.nnz(),
+            Self::Compressed(dataset) => dataset.nnz(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Plain(dataset) => dataset.len(),
+            Self::Compressed(dataset) => dataset.len(),
+        }
+    }
+}
+
+// The number of vectors of a compressed forward index decoded at once when all of them are needed.
+const DECODED_CHUNK_SIZE: usize = 1 << 12;
+
+// The vectors from which posting lists are built again. The vectors of a plain forward index are
+// read in place, while those of a compressed one are decoded list by list, so that the whole
+// forward index is never decompressed at once.
+trait ListVectors: Sync {
+    type Value: PartialOrd + DataType;
+
+    // Calls `f` with a dataset storing the vectors of the documents `doc_ids`, which are sorted and
+    // distinct. The ids of the documents of its vectors are also given to `f`, unless they are the
+    // ids of the vectors themselves. See `vector_id`.
+    fn with_vectors<R>(
+        &self,
+        doc_ids: &[usize],
+        f: impl FnOnce(&SparseDataset<Self::Value>, Option<&[usize]>) -> R,
+    ) -> R;
+}
+
+impl<T> ListVectors for SparseDataset<T>
+where
+    T: PartialOrd + DataType,
+{
+    type Value = T;
+
+    fn with_vectors<R>(
+        &self,
+        _doc_ids: &[usize],
+        f: impl FnOnce(&SparseDataset<T>, Option<&[usize]>) -> R,
+    ) -> R {
+        f(self, None)
+    }
+}
+
+impl ListVectors for CompressedSparseDataset {
+    type Value = f32;
+
+    fn with_vectors<R>(
+        &self,
+        doc_ids: &[usize],
+        f: impl FnOnce(&SparseDataset<f32>, Option<&[usize]>) -> R,
+    ) -> R {
+        let dataset: SparseDataset<f32> = doc_ids.iter().map(|&doc_id| self.get(doc_id)).collect();
+        f(&dataset, Some(doc_ids))
+    }
+}
+
+// Returns the id of the vector of the document `doc_id` in a dataset given by `ListVectors`.
+#[inline]
+fn vector_id(doc_ids: Option<&[usize]>, doc_id: usize) -> usize {
+    doc_ids.map_or(doc_id, |doc_ids| doc_ids.binary_search(&doc_id).unwrap())
+}
+
+// Instead of string doc_ids we store their offsets in the forward_index and the lengths of the vectors
+// This allows us to save the random acceses that would be needed to access exactly these values from the
+// forward index. The values of each doc are packed into a single u64 in `packed_postings`. We use 48 bits for the offset and 16 bits for the lenght. This choice limits the size of the dataset to be 1<<48-1.
+// We use the forward index to convert the offsets of the top-k back to the id of the corresponding documents.
+// Postings can be compressed instead (see `Postings`), in which case the packed values of a block are
+// reconstructed when the block is evaluated.
+#[derive(Default, PartialEq, Debug, Clone, Serialize, Deserialize)]
+struct PostingList {
+    // postings: Box<[usize]>,
+    postings: Postings,
+    block_offsets: Box<[usize]>,
+    // summaries: SparseDataset<f16>,
+    summaries: QuantizedSummary,
+    max_value: f32, // largest value among the postings, an upper bound to their contribution
+    pruned_value: f32, // largest value among the vectors pruned away from the list, 0 if none
+}
+
+impl SpaceUsage for PostingList {
+    fn space_usage_byte(&self) -> usize {
+        self.postings.space_usage_byte()
+            + self.block_offsets.space_usage_byte()
+            + self.summaries.space_usage_byte()
+            + self.max_value.space_usage_byte()
+            + self.pruned_value.space_usage_byte()
+    }
+}
+
+// The postings of a posting list.
+// - `Packed`: a `u64` per posting with the offset and the length of the vector in the forward index.
+// - `Compressed`: the doc ids of each block are sorted and encoded as gaps with variable-length integers.
+//   `block_offsets` are the byte offsets of the blocks in `data`. Offsets and lengths are looked up
+//   in the forward index while decoding.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+enum Postings {
+    Packed(Box<[u64]>),
+    Compressed {
+        data: Box<[u8]>,
+        block_offsets: Box<[usize]>,
+    },
+}
+
+impl Default for Postings {
+    fn default() -> Self {
+        Self::Packed(Box::default())
+    }
+}
+
+impl SpaceUsage for Postings {
+    fn space_usage_byte(&self) -> usize {
+        match self {
+            Self::Packed(packed_postings) => packed_postings.space_usage_byte(),
+            Self::Compressed {
+                data,
+                block_offsets,
+            } => data.space_usage_byte() + block_offsets.space_usage_byte(),
+        }
+    }
+}
+
+impl PostingList {
+    // The number of postings in the list.
+    fn len(&self) -> usize {
+        match &self.postings {
+            Postings::Packed(packed_postings) => packed_postings.len(),
+            Postings::Compressed { .. } => self.block_offsets.last().copied().unwrap_or(0),
+        }
+    }
+
+    fn n_blocks(&self) -> usize {
+        self.block_offsets.len().saturating_sub(1)
+    }
+
+    // Appends to `violations` the violated invariants of the posting list of component `list`.
+    // Postings are checked against the forward index only if `check_postings` is set.
+    fn check<T>(
+        &self,
+        list: usize,
+        forward_index: &ForwardIndex<T>,
+        check_postings: bool,
+        dim: usize,
+        violations: &mut Vec<Violation>,
+    ) where
+        T: DataType,
+    {
+        let n_violations = violations.len();
+
+        let n_postings = self.len();
+        match (self.block_offsets.first(), self.block_offsets.last()) {
+            (Some(&first), Some(&last)) => {
+                if first != 0 {
+                    violations.push(Violation::BlockOffsetsStart { list, first });
+                }
+                for (block, range) in self.block_offsets.windows(2).enumerate() {
+                    if range[0] >= range[1] {
+                        violations.push(Violation::BlockOffsetsNotIncreasing {
+                            list,
+                            block,
+                            start: range[0],
+                            end: range[1],
+                        });
+                    }
+                }
+                if last != n_postings {
+                    violations.push(Violation::BlockOffsetsEnd {
+                        list,
+                        last,
+                        n_postings,
+                    });
+                }
+            }
+            _ if n_postings > 0 => violations.push(Violation::BlockOffsetsEnd {
+                list,
+                last: 0,
+                n_postings,
+            }),
+            _ => {}
+        }
+        let valid_blocks = violations.len() == n_violations;
+
+        if self.summaries.n_summaries() != self.n_blocks() {
+            violations.push(Violation::SummaryCount {
+                list,
+                n_summaries: self.summaries.n_summaries(),
+                n_blocks: self.n_blocks(),
+            });
+        }
+        if !self.summaries.is_consistent(dim) {
+            violations.push(Violation::CorruptedSummaries { list });
+        }
+
+        if !check_postings {
+            return;
+        }
+
+        match &self.postings {
+            Postings::Packed(packed_postings) => {
+                for (position, &pack) in packed_postings.iter().enumerate() {
+                    let (offset, len) = Self::unpack_offset_len(pack);
+                    match forward_index.checked_offset_to_id(offset) {
+                        None => violations.push(Violation::PostingOffset {
+                            list,
+                            position,
+                            offset,
+                        }),
+                        Some(doc_id) if forward_index.vector_len(doc_id) != len => {
+                            violations.push(Violation::PostingLength {
+                                list,
+                                position,
+                                doc_id,
+                                len,
+                                vector_len: forward_index.vector_len(doc_id),
+                            })
+                        }
+                        Some(_) => {}
+                    }
+                }
+            }
+            // Compressed postings can be decoded only if the blocks are valid.
+            Postings::Compressed {
+                data,
+                block_offsets,
+            } if valid_blocks => {
+                for (block, range) in self.block_offsets.windows(2).enumerate() {
+                    let doc_ids = block_offsets
+                        .get(block..block + 2)
+                        .filter(|bytes| bytes[0] <= bytes[1] && bytes[1] <= data.len())
+                        .and_then(|bytes| {
+                            Self::try_decode_block(&data[bytes[0]..bytes[1]], range[1] - range[0])
+                        });
+
+                    let Some(doc_ids) = doc_ids else {
+                        violations.push(Violation::CompressedBlock { list, block });
+                        continue;
+                    };
+                    for (i, &doc_id) in doc_ids.iter().enumerate() {
+                        if doc_id >= forward_index.len() {
+                            violations.push(Violation::PostingDocId {
+                                list,
+                                position: range[0] + i,
+                                doc_id,
+                                n_documents: forward_index.len(),
+                            });
+                        }
+                    }
+                }
+            }
+            Postings::Compressed { .. } => {}
+        }
+    }
+
+    #[inline]
+    fn pack_offset_len(offset: usize, len: usize) -> u64 {
+        ((offset as u64) << 16) | (len as u64)
+    }
+
+    #[inline]
+    fn unpack_offset_len(pack: u64) -> (usize, usize) {
+        ((pack >> 16) as usize, (pack & (u16::MAX as u64)) as usize)
+    }
+
+    // Evaluates the blocks of the list that pass the `heap_factor` test, scanned according to
+    // `block_scan`. Returns whether some block has been skipped.
+    #[allow(clippy::too_many_arguments)]
+    #[inline]
+    pub fn search<T, H>(
+        &self,
+        query: &[f32],
+        query_components: &[u16],
+        query_values: &[f32],
+        k: usize,
+        heap_factor: f32,
+        block_scan: BlockScan,
+        heap: &mut H,
+        visited: &mut HashSet<usize>,
+        forward_index: &ForwardIndex<T>,
+    ) -> bool
+    where
+        T: DataType,
+        H: SearchHeap,
+    {
+        let mut blocks_to_evaluate: Vec<usize> = Vec::new();
+        let mut skipped = false;
+        let mut buffer = Vec::new();
+        let dots = self
+            .summaries
+            .matmul_with_query(query_components, query_values);
+
+        let block_ids = if block_scan == BlockScan::SortedBySummary {
+            let mut sorted_blocks = (0..dots.len()).collect::<Vec<_>>();
+            sorted_blocks.sort_unstable_by(|&a, &b| dots[b].total_cmp(&dots[a]));
+            Either::Left(sorted_blocks.into_iter())
+        } else {
+            Either::Right(0..dots.len())
+        };
+
+        //for (block_id, (c_summary, v_summary)) in self.summaries.iter().enumerate() {
+        //let dot = dot_product_dense_sparse(query, c_summary, v_summary);
+        for block_id in block_ids {
+            let dot = dots[block_id];
+
+            if heap.len() == k && dot < -heap_factor * heap.top() {
+                skipped = true;
+                if block_scan == BlockScan::Full {
+                    continue;
+                }
+                break;
+            }
+
+            if blocks_to_evaluate.len() == 1 {
+                for &cur_block_id in blocks_to_evaluate.iter() {
+                    self.evaluate_posting_block(
+                        query,
+                        query_components,
+                        query_values,
+                        self.packed_block(cur_block_id, forward_index, &mut buffer),
+                        heap,
+                        visited,
+                        forward_index,
+                    );
+                }
+                blocks_to_evaluate.clear();
+            }
+
+            self.prefetch_block(block_id);
+
+            blocks_to_evaluate.push(block_id);
+        }
+
+        for &cur_block_id in blocks_to_evaluate.iter() {
+            self.evaluate_posting_block(
+                query,
+                query_components,
+                query_values,
+                self.packed_block(cur_block_id, forward_index, &mut buffer),
+                heap,
+                visited,
+                forward_index,
+            );
+        }
+
+        skipped
+    }
+
+    // Returns the packed postings of the block `block_id`. Compressed postings are decoded into `buffer`.
+    #[inline]
+    fn packed_block<'a, T>(
+        &'a self,
+        block_id: usize,
+        forward_index: &ForwardIndex<T>,
+        buffer: &'a mut Vec<u64>,
+    ) -> &'a [u64]
+    where
+        T: DataType,
+    {
+        match &self.postings {
+            Postings::Packed(packed_postings) => {
+                &packed_postings[self.block_offsets[block_id]..self.block_offsets[block_id + 1]]
+            }
+            Postings::Compressed {
+                data,
+                block_offsets,
+            } => {
+                let n = self.block_offsets[block_id + 1] - self.block_offsets[block_id];
+                let mut block = &data[block_offsets[block_id]..block_offsets[block_id + 1]];
+
+                buffer.clear();
+                let mut doc_id = 0;
+                for _ in 0..n {
+                    let (gap, rest) = decode_varint(block);
+                    block = rest;
+                    doc_id += gap as usize;
+                    buffer.push(Self::pack_offset_len(
+                        forward_index.vector_offset(doc_id),
+                        forward_index.vector_len(doc_id),
+                    ));
+                }
+
+                buffer
+            }
+        }
+    }
+
+    #[inline]
+    fn prefetch_block(&self, block_id: usize) {
+        match &self.postings {
+            Postings::Packed(packed_postings) => {
+                let packed_posting_block = &packed_postings
+                    [self.block_offsets[block_id]..self.block_offsets[block_id + 1]];
+
+                for i in (0..packed_posting_block.len()).step_by(8) {
+                    prefetch_read_NTA(packed_posting_block, i);
+                }
+            }
+            Postings::Compressed {
+                data,
+                block_offsets,
+            } => {
+                for i in (block_offsets[block_id]..block_offsets[block_id + 1]).step_by(64) {
+                    prefetch_read_NTA(data, i);
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[inline]
+    fn evaluate_posting_block<T, H>(
+        &self,
+        query: &[f32],
+        query_term_ids: &[u16],
+        query_values: &[f32],
+        packed_posting_block: &[u64],
+        heap: &mut H,
+        visited: &mut HashSet<usize>,
+        forward_index: &ForwardIndex<T>,
+    ) where
+        T: DataType,
+        H: SearchHeap,
+    {
+        let (mut prev_offset, mut prev_len) = Self::unpack_offset_len(packed_posting_block[0]);
+
+        for &pack in packed_posting_block.iter().skip(1) {
+            let (offset, len) = Self::unpack_offset_len(pack);
+            forward_index.prefetch_vec_with_offset(offset, len);
+
+            if !visited.contains(&prev_offset) {
+                let distance = forward_index.dot_product_with_offset(
+                    query,
+                    query_term_ids,
+                    query_values,
+                    prev_offset,
+                    prev_len,
+                );
+
+                visited.insert(prev_offset);
+                heap.push_with_id(-distance, prev_offset);
+            }
+
+            prev_offset = offset;
+            prev_len = len;
+        }
+
+        if visited.contains(&prev_offset) {
+            return;
+        }
+
+        let distance = forward_index.dot_product_with_offset(
+            query,
+            query_term_ids,
+            query_values,
+            prev_offset,
+            prev_len,
+        );
+
+        visited.insert(prev_offset);
+        heap.push_with_id(-distance, prev_offset);
+    }
+
+    /// Gets a posting list already pruned and represents it by using a blocking
+    /// strategy to partition postings into block and a summarization strategy to
+    /// represents the summary of each block.
+    /// The offsets and lengths of the postings refer to `forward_index`.
+    // The postings refer to the vectors in `forward_index`, while blocks and summaries are
+    // computed on `dataset`, which stores the same vectors possibly with a different precision.
+    pub fn build<T, F>(
+        dataset: &SparseDataset<T>,
+        forward_index: &ForwardIndex<F>,
+        postings: &[(T, usize)],
+        config: &Configuration,
+    ) -> Self
+    where
+        T: PartialOrd + DataType,
+        F: DataType,
+    {
+        Self::build_with_doc_ids(dataset, None, forward_index, postings, config)
+    }
+
+    // Same as `build`, but the ids of the postings refer to the vectors of `dataset`, which are
+    // the documents `doc_ids` of `forward_index`, if given.
+    fn build_with_doc_ids<T, F>(
+        dataset: &SparseDataset<T>,
+        doc_ids: Option<&[usize]>,
+        forward_index: &ForwardIndex<F>,
+        postings: &[(T, usize)],
+        config: &Configuration,
+    ) -> Self
+    where
+        T: PartialOrd + DataType,
+        F: DataType,
+    {
+        let mut posting_list: Vec<_> = postings.iter().map(|(_, docid)| *docid).collect();
+        let max_value = postings
+            .iter()
+            .fold(0_f32, |max, (score, _)| max.max(score.to_f32().unwrap()));
+
+        let block_offsets = match config.blocking {
+            BlockingStrategy::FixedSize { block_size } => {
+                Self::fixed_size_blocking(&posting_list, block_size)
+            }
+
+            BlockingStrategy::RandomKmeans {
+                centroid_fraction,
+                truncated_kmeans_training,
+                truncation_size,
+                min_cluster_size,
+            } => Self::blocking_with_random_kmeans(
+                &mut posting_list,
+                centroid_fraction,
+                truncated_kmeans_training,
+                truncation_size,
+                min_cluster_size,
+                dataset,
+            ),
+        };
+
+        let mut block_summaries = Self::summarize_blocks(
+            dataset,
+            &posting_list,
+            &block_offsets,
+            &config.summarization,
+        );
+
+        let block_offsets = match config.block_order {
+            BlockOrder::Clustering => block_offsets,
+            BlockOrder::MaxSummaryWeight => Self::sort_blocks_by_max_summary_weight(
+                &mut posting_list,
+                &block_offsets,
+                &mut block_summaries,
+            ),
+        };
+
+        if let Some(doc_ids) = doc_ids {
+            posting_list
+                .iter_mut()
+                .for_each(|vector_id| *vector_id = doc_ids[*vector_id]);
+        }
+        let postings = Self::encode_postings(
+            &mut posting_list,
+            &block_offsets,
+            forward_index,
+            &config.postings,
+        );
+
+        Self {
+            postings,
+            block_offsets: block_offsets.into_boxed_slice(),
+            summaries: Self::quantize_summaries(&block_summaries, forward_index.dim()),
+            max_value,
+            pruned_value: 0.0,
+        }
+    }
+
+    // Replaces the summaries of the blocks with those computed with the summarization strategy
+    // of `config`. Blocks are reordered if required by the block order of `config`.
+    // The vectors of `dataset` are the documents `doc_ids` of `forward_index`, if given.
+    fn resummarize<T, F>(
+        &mut self,
+        dataset: &SparseDataset<T>,
+        doc_ids: Option<&[usize]>,
+        forward_index: &ForwardIndex<F>,
+        config: &Configuration,
+    ) where
+        T: PartialOrd + DataType,
+        F: DataType,
+    {
+        let mut posting_list = self.doc_ids(forward_index);
+        let vector_ids: Vec<_> = posting_list
+            .iter()
+            .map(|&doc_id| vector_id(doc_ids, doc_id))
+            .collect();
+        let mut block_summaries = Self::summarize_blocks(
+            dataset,
+            &vector_ids,
+            &self.block_offsets,
+            &config.summarization,
+        );
+
+        if config.block_order == BlockOrder::MaxSummaryWeight {
+            let block_offsets = Self::sort_blocks_by_max_summary_weight(
+                &mut posting_list,
+                &self.block_offsets,
+                &mut block_summaries,
+            );
+            self.postings = Self::encode_postings(
+                &mut posting_list,
+                &block_offsets,
+                forward_index,
+                &config.postings,
+            );
+            self.block_offsets = block_offsets.into_boxed_slice();
+        }
+
+        self.summaries = Self::quantize_summaries(&block_summaries, forward_index.dim());
+    }
+
+    // Computes the summary of each block of the posting list.
+    fn summarize_blocks<T>(
+        dataset: &SparseDataset<T>,
+        posting_list: &[usize],
+        block_offsets: &[usize],
+        summarization: &SummarizationStrategy,
+    ) -> Vec<(Vec<u16>, Vec<T>)>
+    where
+        T: PartialOrd + DataType,
+    {
+        block_offsets
+            .windows(2)
+            .map(|block_range| match *summarization {
+                SummarizationStrategy::FixedSize { n_components } => Self::fixed_size_summary(
+                    dataset,
+                    &posting_list[block_range[0]..block_range[1]],
+                    n_components,
+                ),
+
+                SummarizationStrategy::EnergyPerserving {
+                    summary_energy: fraction,
+                } => Self::energy_preserving_summary(
+                    dataset,
+                    &posting_list[block_range[0]..block_range[1]],
+                    fraction,
+                ),
+            })
+            .collect()
+    }
+
+    fn quantize_summaries<T>(block_summaries: &[(Vec<u16>, Vec<T>)], dim: usize) -> QuantizedSummary
+    where
+        T: DataType,
+    {
+        let mut summaries = SparseDatasetMut::<T>::new();
+        for (components, values) in block_summaries.iter() {
+            summaries.push(components, values);
+        }
+
+        QuantizedSummary::new(SparseDataset::<T>::from(summaries).quantize_f16(), dim)
+    }
+
+    // Reorders the blocks by decreasing largest value of their summary. Returns the new block offsets.
+    fn sort_blocks_by_max_summary_weight<T>(
+        posting_list: &mut Vec<usize>,
+        block_offsets: &[usize],
+        block_summaries: &mut Vec<(Vec<u16>, Vec<T>)>,
+    ) -> Vec<usize>
+    where
+        T: PartialOrd + DataType,
+    {
+        let max_weight = |values: &[T]| {
+            values
+                .iter()
+                .fold(0_f32, |max, v| max.max(v.to_f32().unwrap()))
+        };
+
+        let mut order: Vec<_> = (0..block_summaries.len()).collect();
+        order.sort_by(|&a, &b| {
+            max_weight(&block_summaries[b].1).total_cmp(&max_weight(&block_summaries[a].1))
+        });
+
+        let mut reordered_posting_list = Vec::with_capacity(posting_list.len());
+        let mut reordered_block_offsets = Vec::with_capacity(block_offsets.len());
+        reordered_block_offsets.push(0);
+        for &block_id in order.iter() {
+            reordered_posting_list.extend_from_slice(
+                &posting_list[block_offsets[block_id]..block_offsets[block_id + 1]],
+            );
+            reordered_block_offsets.push(reordered_posting_list.len());
+        }
+
+        let mut summaries: Vec<_> = std::mem::take(block_summaries)
+            .into_iter()
+            .map(Some)
+            .collect();
+        *block_summaries = order
+            .iter()
+            .map(|&block_id| summaries[block_id].take().unwrap())
+            .collect();
+        *posting_list = reordered_posting_list;
+
+        reordered_block_offsets
+    }
+
+    // Moves the list to another forward index, where its postings, block by block, refer to the
+    // vectors `doc_ids`. Blocks and summaries are kept.
+    fn remap<T>(
+        mut self,
+        doc_ids: &mut [usize],
+        forward_index: &ForwardIndex<T>,
+        strategy: &PostingsStrategy,
+    ) -> Self
+    where
+        T: DataType,
+    {
+        self.postings =
+            Self::encode_postings(doc_ids, &self.block_offsets, forward_index, strategy);
+        self.summaries.extend_dim(forward_index.dim());
+        self
+    }
+
+    // Encodes the doc ids of the postings, already divided into blocks, according to `strategy`.
+    fn encode_postings<T>(
+        posting_list: &mut [usize],
+        block_offsets: &[usize],
+        forward_index: &ForwardIndex<T>,
+        strategy: &PostingsStrategy,
+    ) -> Postings
+    where
+        T: DataType,
+    {
+        match strategy {
+            PostingsStrategy::Packed => {
+                let packed_postings: Vec<_> = posting_list
+                    .iter()
+                    .map(|&doc_id| {
+                        Self::pack_offset_len(
+                            forward_index.vector_offset(doc_id),
+                            forward_index.vector_len(doc_id),
+                        )
+                    })
+                    .collect();
+
+                Postings::Packed(packed_postings.into_boxed_slice())
+            }
+            PostingsStrategy::Compressed => Self::compress_postings(posting_list, block_offsets),
+        }
+    }
+
+    // Returns the doc ids of the postings, block by block.
+    fn doc_ids<T>(&self, forward_index: &ForwardIndex<T>) -> Vec<usize>
+    where
+        T: DataType,
+    {
+        match &self.postings {
+            Postings::Packed(packed_postings) => packed_postings
+                .iter()
+                .map(|&pack| forward_index.offset_to_id(Self::unpack_offset_len(pack).0))
+                .collect(),
+            Postings::Compressed {
+                data,
+                block_offsets,
+            } => {
+                let mut doc_ids = Vec::with_capacity(self.len());
+                for (block, range) in self.block_offsets.windows(2).enumerate() {
+                    let mut block_data = &data[block_offsets[block]..block_offsets[block + 1]];
+                    let mut doc_id = 0;
+                    for _ in range[0]..range[1] {
+                        let (gap, rest) = decode_varint(block_data);
+                        block_data = rest;
+                        doc_id += gap as usize;
+                        doc_ids.push(doc_id);
+                    }
+                }
+                doc_ids
+            }
+        }
+    }
+
+    // Sorts the doc ids within each block and encodes them as gaps with variable-length integers.
+    fn compress_postings(posting_list: &mut [usize], block_offsets: &[usize]) -> Postings {
+        let mut data = Vec::new();
+        let mut compressed_block_offsets = Vec::with_capacity(block_offsets.len());
+
+        compressed_block_offsets.push(0);
+        for block_range in block_offsets.windows(2) {
+            let block = &mut posting_list[block_range[0]..block_range[1]];
+            block.sort_unstable();
+
+            let mut prev = 0;
+            for &doc_id in block.iter() {
+                encode_varint((doc_id - prev) as u64, &mut data);
+                prev = doc_id;
+            }
+            compressed_block_offsets.push(data.len());
+        }
+
+        Postings::Compressed {
+            data: data.into_boxed_slice(),
+            block_offsets: compressed_block_offsets.into_boxed_slice(),
+        }
+    }
+
+    // Decodes the `n` doc ids of a compressed block. Returns `None` if `data` does not
+    // consist of exactly `n` gaps.
+    fn try_decode_block(mut data: &[u8], n: usize) -> Option<Vec<usize>> {
+        let mut doc_ids = Vec::with_capacity(n);
+        let mut doc_id = 0_usize;
+        for _ in 0..n {
+            let (gap, rest) = try_decode_varint(data)?;
+            doc_id = doc_id.checked_add(usize::try_from(gap).ok()?)?;
+            doc_ids.push(doc_id);
+            data = rest;
+        }
+
+        data.is_empty().then_some(doc_ids)
+    }
+
+    // ** Blocking strategies **
+
+    fn fixed_size_blocking(posting_list: &[usize], block_size: usize) -> Vec<usize> {
+        // of course this strategy would not need offsets, but we are using them
+        // just to have just one, "universal" query search implementation
+        // The last block takes the remaining postings, and a list shorter than `block_size`
+        // forms a single block.
+        if posting_list.is_empty() {
+            return Vec::new();
+        }
+
+        let mut block_offsets: Vec<_> = (0..(posting_list.len() / block_size).max(1))
+            .map(|i| i * block_size)
+            .collect();
+
+        block_offsets.push(posting_list.len());
+        block_offsets
+    }
+
+    fn blocking_with_random_kmeans<T: DataType>(
+        posting_list: &mut [usize],
+        centroid_fraction: f32,
+        truncated_kmeans_training: bool,
+        _truncation_size: usize,
+        min_cluster_size: usize,
+        dataset: &SparseDataset<T>,
+    ) -> Vec<usize> {
+        if posting_list.is_empty() {
+            return Vec::new();
+        }
+
+        let n_centroids = ((centroid_fraction * posting_list.len() as f32) as usize).max(1);
+        let mut reordered_posting_list = Vec::<_>::with_capacity(posting_list.len());
+        let mut block_offsets = Vec::with_capacity(n_centroids);
+
+        if truncated_kmeans_training {
+            // Need to change only how clustering results is computed
+            todo!();
+        } else {
+            let clustering_results =
+                do_random_kmeans_on_docids(posting_list, n_centroids, dataset, min_cluster_size);
+
+            block_offsets.push(0);
+
+            for cluster in clustering_results {
+                if cluster.is_empty() {
+                    continue;
+                }
+                reordered_posting_list.extend(cluster);
+                block_offsets.push(reordered_posting_list.len());
+            }
+
+            assert_eq!(reordered_posting_list.len(), posting_list.len());
+            posting_list.copy_from_slice(&reordered_posting_list);
+        }
+
+        block_offsets
+    }
+
+    // ** Summarization strategies **
+
+    fn fixed_size_summary<T>(
+        dataset: &SparseDataset<T>,
+        block: &[usize],
+        n_components: usize,
+    ) -> (Vec<u16>, Vec<T>)
+    where
+        T: PartialOrd + DataType,
+    {
+        let mut hash = HashMap::new();
+        for &doc_id in block.iter() {
+            // for each component_id, store the largest value seen so far
+            for (&c, &v) in dataset.iter_vector(doc_id) {
+                hash.entry(c)
+                    .and_modify(|h| *h = if *h < v { v } else { *h })
+                    .or_insert(v);
+            }
+        }
+
+        let mut components_values: Vec<_> = hash.iter().collect();
+
+        // First sort by decreasing scores, then take only up to LIMIT and sort by component_id
+        components_values.sort_unstable_by(|a, b| b.1.partial_cmp(a.1).unwrap());
+
+        components_values.truncate(n_components);
+
+        components_values.sort_unstable_by(|a, b| a.0.cmp(b.0)); // sort by id to make binary search possible
+
+        let components: Vec<_> = components_values
+            .iter()
+            .map(|(&component_id, _score)| component_id)
+            .collect();
+
+        let values: Vec<_> = components.iter().copied().map(|k| hash[&k]).collect();
+
+        (components, values)
+    }
+
+    fn energy_preserving_summary<T>(
+        dataset: &SparseDataset<T>,
+        block: &[usize],
+        fraction: f32,
+    ) -> (Vec<u16>, Vec<T>)
+    where
+        T: PartialOrd + DataType,
+    {
+        let mut hash = HashMap::new();
+        for &doc_id in block.iter() {
+            // for each component_id, store the largest value seen so far
+            for (&c, &v) in dataset.iter_vector(doc_id) {
+                hash.entry(c)
+                    .and_modify(|h| *h = if *h < v { v } else { *h })
+                    .or_insert(v);
+            }
+        }
+
+        let mut components_values: Vec<_> = hash.iter().collect();
+
+        components_values.sort_unstable_by(|a, b| b.1.partial_cmp(a.1).unwrap());
+        let total_sum = components_values
+            .iter()
+            .fold(0_f32, |sum, (_, &x)| sum + x.to_f32().unwrap());
+
+        let mut term_ids = Vec::new();
+        let mut values = Vec::new();
+        let mut acc = 0_f32;
+        for (&tid, &v) in components_values.iter() {
+            acc += v.to_f32().unwrap();
+            term_ids.push(tid);
+            values.push(v);
+            if (acc / total_sum) > fraction {
+                break;
+            }
+        }
+        term_ids.sort();
+        let values: Vec<T> = term_ids.iter().copied().map(|k| hash[&k]).collect();
+        (term_ids, values)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+/// Represents the possible choices for the strategy used to prune the posting
+/// lists at building time.
+/// There are the following possible strategies:
+/// - `Fixed  { n_postings: usize }`: Every posting list is pruned by taking its top-`n_postings`
+/// - `GlobalThreshold { n_postings: usize, max_fraction: f32 }`: We globally select a threshold and we prune all the postings with smaller score. The threshold is chosen so that every posting list has `n_postings` on average. We limit the number of postings per list to `max_fraction*n_postings`. If several postings have a score equal to the threshold, they are kept from the posting lists of the smallest components first, until the overall number of postings is reached.
+/// - `EnergyPreserving { fraction: f32 }`: Every posting list is pruned by taking its top postings until their scores sum up to `fraction` of the scores of the whole list.
+/// - `Threshold { threshold: f32 }`: We prune all the postings whose score is smaller than `threshold`.
+/// - `DocumentCentric { n_components: usize }`: Every document is kept only in the posting lists of its top-`n_components` components.
+pub enum PruningStrategy {
+    FixedSize {
+        n_postings: usize,
+    },
+    GlobalThreshold {
+        n_postings: usize,
+        max_fraction: f32, // limits the length of each posting list to max_fraction*n_postings
+    },
+    EnergyPreserving {
+        fraction: f32,
+    },
+    Threshold {
+        threshold: f32,
+    },
+    DocumentCentric {
+        n_components: usize,
+    },
+}
+
+impl Default for PruningStrategy {
+    fn default() -> Self {
+        Self::FixedSize { n_postings: 3500 }
+    }
+}
+
+impl PruningStrategy {
+    /// Returns whether pruning with `self` keeps a subset of the postings kept by `other`, i.e.,
+    /// both strategies are of the same kind and `self` is at least as aggressive as `other`.
+    /// Strategies of different kinds are not comparable.
+    ///
+    /// # Example
+    /// ```
+    /// use seismic::inverted_index::PruningStrategy;
+    ///
+    /// let tight = PruningStrategy::Threshold { threshold: 0.5 };
+    /// let loose = PruningStrategy::Threshold { threshold: 0.1 };
+    ///
+    /// assert!(tight.is_as_tight_as(&loose));
+    /// assert!(!loose.is_as_tight_as(&tight));
+    /// assert!(!tight.is_as_tight_as(&PruningStrategy::FixedSize { n_postings: 10 }));
+    /// ```
+    #[must_use]
+    pub fn is_as_tight_as(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::FixedSize { n_postings }, Self::FixedSize { n_postings: other }) => {
+                n_postings <= other
+            }
+            (
+                Self::GlobalThreshold {
+                    n_postings,
+                    max_fraction,
+                },
+                Self::GlobalThreshold {
+                    n_postings: other_n_postings,
+                    max_fraction: other_max_fraction,
+                },
+            ) => {
+                n_postings <= other_n_postings
+                    && max_fraction * *n_postings as f32
+                        <= other_max_fraction * *other_n_postings as f32
+            }
+            (Self::EnergyPreserving { fraction }, Self::EnergyPreserving { fraction: other }) => {
+                fraction <= other
+            }
+            (Self::Threshold { threshold }, Self::Threshold { threshold: other }) => {
+                threshold >= other
+            }
+            (
+                Self::DocumentCentric { n_components },
+                Self::DocumentCentric {
+                    n_components: other,
+                },
+            ) => n_components <= other,
+            _ => false,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+/// Represents the possible choices for the strategy used to divide each posting list into blocks.
+/// - `FixedSize { block_size: usize }`: The postings, sorted by decreasing score, are split into consecutive blocks of `block_size` postings. The last block takes the remaining postings.
+/// - `RandomKmeans { centroid_fraction: f32, truncated_kmeans_training: bool, truncation_size: usize, min_cluster_size: usize }`: The postings are assigned to random centroids, `centroid_fraction` times their number, and each cluster becomes a block. The postings of clusters with at most `min_cluster_size` postings are reassigned to the larger clusters.
+pub enum BlockingStrategy {
+    FixedSize {
+        block_size: usize,
+    },
+
+    RandomKmeans {
+        centroid_fraction: f32,
+        truncated_kmeans_training: bool,
+        truncation_size: usize,
+        min_cluster_size: usize,
+    },
+}
+
+impl Default for BlockingStrategy {
+    fn default() -> Self {
+        BlockingStrategy::RandomKmeans {
+            centroid_fraction: 0.1,
+            truncated_kmeans_training: false,
+            truncation_size: 32,
+            min_cluster_size: 2,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum SummarizationStrategy {
+    FixedSize { n_components: usize },
+    EnergyPerserving { summary_energy: f32 },
+}
+
+impl Default for SummarizationStrategy {
+    fn default() -> Self {
+        Self::EnergyPerserving {
+            summary_energy: 0.4,
+        }
+    }
+}
+
+/// Represents the possible choices for the strategy used to select the components of
+/// a query whose posting lists are evaluated at query time.
+/// Components are always considered by decreasing value.
+/// - `FixedSize { n_components }`: Selects the top `n_components` components.
+/// - `L1Energy { fraction }`: Selects components until their sum covers at least `fraction` of the L1 norm of the query.
+/// - `L2Energy { fraction }`: Selects components until their sum of squares covers at least `fraction` of the squared L2 norm of the query.
+/// - `MinValue { threshold }`: Selects the components whose value is at least `threshold`.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum QueryPruning {
+    FixedSize { n_components: usize },
+    L1Energy { fraction: f32 },
+    L2Energy { fraction: f32 },
+    MinValue { threshold: f32 },
+}
+
+impl Default for QueryPruning {
+    fn default() -> Self {
+        Self::FixedSize { n_components: 10 }
+    }
+}
+
+impl QueryPruning {
+    /// Returns the selected components of the query, sorted by decreasing value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use seismic::inverted_index::QueryPruning;
+    ///
+    /// let components = [0, 1, 2, 3];
+    /// let values = [1.0, 4.0, 3.0, 2.0];
+    ///
+    /// let fixed = QueryPruning::FixedSize { n_components: 2 };
+    /// assert_eq!(fixed.select_components(&components, &values), vec![1, 2]);
+    ///
+    /// let energy = QueryPruning::L1Energy { fraction: 0.8 };
+    /// assert_eq!(energy.select_components(&components, &values), vec![1, 2, 3]);
+    ///
+    /// let threshold = QueryPruning::MinValue { threshold: 2.5 };
+    /// assert_eq!(threshold.select_components(&components, &values), vec![1, 2]);
+    /// ```
+    #[must_use]
+    pub fn select_components(&self, query_components: &[u16], query_values: &[f32]) -> Vec<u16> {
+        let sorted: Vec<_> = query_components
+            .iter()
+            .zip(query_values)
+            .sorted_unstable_by(|a, b| b.1.partial_cmp(a.1).unwrap())
+            .collect();
+
+        let n_components = match *self {
+            Self::FixedSize { n_components } => n_components,
+            Self::L1Energy { fraction } => {
+                Self::energy_cut(sorted.iter().map(|(_, v)| v.abs()), fraction)
+            }
+            Self::L2Energy { fraction } => {
+                Self::energy_cut(sorted.iter().map(|(_, v)| *v * *v), fraction)
+            }
+            Self::MinValue { threshold } => {
+                sorted.iter().take_while(|(_, &v)| v >= threshold).count()
+            }
+        };
+
+        sorted
+            .into_iter()
+            .take(n_components)
+            .map(|(&c, _)| c)
+            .collect()
+    }
+
+    // Returns the smallest number of leading `masses` whose sum is at least `fraction` of the total.
+    fn energy_cut(masses: impl Iterator<Item = f32> + Clone, fraction: f32) -> usize {
+        let total: f32 = masses.clone().sum();
+
+        let mut acc = 0.0;
+        for (i, mass) in masses.enumerate() {
+            acc += mass;
+            if acc >= fraction * total {
+                return i + 1;
+            }
+        }
+
+        usize::MAX
+    }
+}
+
+/// Represents the possible choices for the order in which the blocks of the selected posting lists are evaluated at query time.
+/// - `ListByList`: Posting lists are visited one after the other by decreasing query value, and the blocks of each list in storage order.
+/// - `BestFirst`: The summaries of the blocks of all the selected lists are scored up front. Blocks are then evaluated by decreasing estimated dot product, and the search stops at the first block that fails the `heap_factor` test. Dynamic termination does not apply to this traversal.
+#[derive(Default, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum BlockTraversal {
+    #[default]
+    ListByList,
+    BestFirst,
+}
+
+/// Represents the possible choices for how the blocks of a posting list are scanned at query time.
+/// - `Full`: Blocks are scanned in storage order and every block is tested against the `heap_factor` threshold.
+/// - `SortedBySummary`: Blocks are sorted by their estimated dot product with the query, and the scan stops at the first block below the threshold.
+/// - `EarlyBreak`: Blocks are scanned in storage order and the scan stops at the first block below the threshold. This is meant for indexes whose blocks are statically sorted, see [`BlockOrder::MaxSummaryWeight`]. The order of the blocks of a list is fixed and does not depend on the query, so a block below the threshold may be followed by better blocks, which are never evaluated. Thus `EarlyBreak` can lose recall with respect to `Full` with the same `heap_factor`, even with a `heap_factor` of 0 if the estimated dot products can be negative, e.g., with negative query values.
+#[derive(Default, PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum BlockScan {
+    #[default]
+    Full,
+    SortedBySummary,
+    EarlyBreak,
+}
+
+/// Represents the possible choices for scoring a document from the scores of its passages.
+/// - `MaxPassage`: A document is scored with its best passage.
+/// - `SumTopPassages { m: usize }`: A document is scored with the sum of the scores of its top-`m` passages.
+///
+/// Only the passages evaluated by the search contribute to the score of a document.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum DocumentAggregation {
+    MaxPassage,
+    SumTopPassages { m: usize },
+}
+
+/// Represents the reasons why two indexes cannot be merged, see [`InvertedIndex::merge`].
+/// - `DifferentConfigurations`: The indexes have been built with different configurations.
+/// - `RerankDatasetMismatch`: Only one of the indexes stores the vectors to rerank the results.
+/// - `PassageDocumentsMismatch`: Only one of the indexes stores the documents of its passages.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum MergeError {
+    DifferentConfigurations,
+    RerankDatasetMismatch,
+    PassageDocumentsMismatch,
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DifferentConfigurations => {
+                write!(
+                    f,
+                    "only indexes built with the same configuration can be merged"
+                )
+            }
+            Self::RerankDatasetMismatch => {
+                write!(f, "only one of the indexes has a rerank dataset")
+            }
+            Self::PassageDocumentsMismatch => {
+                write!(
+                    f,
+                    "only one of the indexes has the documents of its passages"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// The error returned by [`InvertedIndex::reprune`] when the requested pruning strategy may keep
+/// postings that the current one has pruned. The index is given back unchanged.
+pub struct RepruneError<T>
+where
+    T: PartialOrd + DataType,
+{
+    pub index: Box<InvertedIndex<T>>,
+    pub pruning: PruningStrategy,
+}
+
+impl<T> fmt::Debug for RepruneError<T>
+where
+    T: PartialOrd + DataType,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RepruneError")
+            .field("current", self.index.config().pruning())
+            .field("pruning", &self.pruning)
+            .finish()
+    }
+}
+
+impl<T> fmt::Display for RepruneError<T>
+where
+    T: PartialOrd + DataType,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the pruning strategy {:?} is not at least as tight as the current one, {:?}",
+            self.pruning,
+            self.index.config().pruning()
+        )
+    }
+}
+
+impl<T> std::error::Error for RepruneError<T> where T: PartialOrd + DataType {}
+
+// The top-k selector used by the search. As in `HeapFaiss`, distances are negated dot products
+// and `top` is the largest distance in the current top-k.
+trait SearchHeap {
+    fn len(&self) -> usize;
+
+    fn top(&self) -> f32;
+
+    // Pushes the vector at `offset` in the forward index.
+    fn push_with_id(&mut self, distance: f32, offset: usize);
+}
+
+impl SearchHeap for HeapFaiss {
     #[inline]
-    pub fn search<T>(
-        &self,
-        query: &[f32],
-        query_components: &[u16],
-        query_values: &[f32],
+    fn len(&self) -> usize {
+        HeapFaiss::len(self)
+    }
+
+    #[inline]
+    fn top(&self) -> f32 {
+        HeapFaiss::top(self)
+    }
+
+    #[inline]
+    fn push_with_id(&mut self, distance: f32, offset: usize) {
+        OnlineTopKSelector::push_with_id(self, distance, offset)
+    }
+}
+
+// Keeps the top-k documents whose passages are pushed. Passages of the same document are
+// collapsed as they are pushed: the score of the document is updated and the document
+// appears at most once in the top-k.
+struct DocumentHeap<'a, T>
+where
+    T: DataType,
+{
+    k: usize,
+    m: usize,
+    passage_documents: &'a [usize],
+    forward_index: &'a ForwardIndex<T>,
+    // The best `m` passage scores of every document seen so far, by decreasing score.
+    passage_scores: HashMap<usize, Vec<f32>>,
+    topk: BTreeSet<ScoredDocument>,
+}
+
+impl<'a, T> DocumentHeap<'a, T>
+where
+    T: DataType,
+{
+    fn new(
         k: usize,
-        heap_factor: f32,
-        heap: &mut HeapFaiss,
-        visited: &mut HashSet<usize>,
-        forward_index: &SparseDataset<T>,
-    ) where
-        T: DataType,
-    {
-        let mut blocks_to_evaluate: Vec<&[u64]> = Vec::new();
-        let dots = self
-            .summaries
-            .matmul_with_query(query_components, query_values);
-        //for (block_id, (c_summary, v_summary)) in self.summaries.iter().enumerate() {
-        //let dot = dot_product_dense_sparse(query, c_summary, v_summary);
-        for (block_id, &dot) in dots.iter().enumerate() {
-            if heap.len() == k && dot < -heap_factor * heap.top() {
-                continue;
+        aggregation: &DocumentAggregation,
+        passage_documents: &'a [usize],
+        forward_index: &'a ForwardIndex<T>,
+    ) -> Self {
+        let m = match *aggregation {
+            DocumentAggregation::MaxPassage => 1,
+            DocumentAggregation::SumTopPassages { m } => m.max(1),
+        };
+
+        Self {
+            k,
+            m,
+            passage_documents,
+            forward_index,
+            passage_scores: HashMap::new(),
+            topk: BTreeSet::new(),
+        }
+    }
+
+    // Returns the top-k documents with their scores, by decreasing score.
+    fn topk(&self) -> Vec<(f32, usize)> {
+        self.topk
+            .iter()
+            .rev()
+            .map(|document| (document.score, document.doc_id))
+            .collect()
+    }
+}
+
+impl<T> SearchHeap for DocumentHeap<'_, T>
+where
+    T: DataType,
+{
+    #[inline]
+    fn len(&self) -> usize {
+        self.topk.len()
+    }
+
+    #[inline]
+    fn top(&self) -> f32 {
+        -self.topk.first().unwrap().score
+    }
+
+    fn push_with_id(&mut self, distance: f32, offset: usize) {
+        let doc_id = self.passage_documents[self.forward_index.offset_to_id(offset)];
+
+        let scores = self.passage_scores.entry(doc_id).or_default();
+        let old_score: f32 = scores.iter().sum();
+
+        let position = scores.partition_point(|&score| score >= -distance);
+        if position >= self.m {
+            return;
+        }
+        scores.insert(position, -distance);
+        scores.truncate(self.m);
+        let score: f32 = scores.iter().sum();
+
+        let old_document = ScoredDocument {
+            score: old_score,
+            doc_id,
+        };
+        let document = ScoredDocument { score, doc_id };
+
+        // A document already in the top-k only improves its score.
+        if self.topk.remove(&old_document) || self.topk.len() < self.k {
+            self.topk.insert(document);
+        } else if document > *self.topk.first().unwrap() {
+            self.topk.pop_first();
+            self.topk.insert(document);
+        }
+    }
+}
+
+// A document with its score, ordered by the latter.
+struct ScoredDocument {
+    score: f32,
+    doc_id: usize,
+}
+
+impl PartialEq for ScoredDocument {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ScoredDocument {}
+
+impl PartialOrd for ScoredDocument {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScoredDocument {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(self.doc_id.cmp(&other.doc_id))
+    }
+}
+
+// A block of a posting list with its estimated dot product, ordered by the latter.
+struct ScoredBlock {
+    dot: f32,
+    component_id: u16,
+    block_id: usize,
+}
+
+impl PartialEq for ScoredBlock {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ScoredBlock {}
+
+impl PartialOrd for ScoredBlock {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScoredBlock {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dot.total_cmp(&other.dot)
+    }
+}
+
+/// Represents the possible choices for the representation of the forward index.
+/// - `Uncompressed`: Vectors are stored as they are, with a `u16` component and a value of type `T` per non-zero.
+/// - `Compressed { quantization }`: Components are delta-encoded and bit-packed, values are scalar quantized with 8 or 4 bits. Vectors are decoded on the fly at query time. See [`CompressedSparseDataset`].
+#[derive(Default, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum ForwardIndexStrategy {
+    #[default]
+    Uncompressed,
+    Compressed {
+        quantization: ValueQuantization,
+    },
+}
+
+/// Represents the possible choices for the order of the blocks within each posting list.
+/// - `Clustering`: Blocks are stored in the order produced by the blocking strategy.
+/// - `MaxSummaryWeight`: Blocks are sorted by decreasing largest value of their summary. Combined with [`BlockScan::EarlyBreak`], the most promising blocks are evaluated first and the scan stops early.
+#[derive(Default, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum BlockOrder {
+    #[default]
+    Clustering,
+    MaxSummaryWeight,
+}
+
+/// Represents the possible choices for the representation of the postings of each posting list.
+/// - `Packed`: Every posting is a `u64` which packs the offset and the length of its vector in the forward index.
+/// - `Compressed`: The doc ids of each block are sorted and gap-encoded with variable-length integers. Offsets and lengths of the vectors are looked up in the forward index when a block is evaluated.
+#[derive(Default, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum PostingsStrategy {
+    #[default]
+    Packed,
+    Compressed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // A small random dataset where every vector has between 5 and 30 components out of 200.
+    fn random_dataset(n_vecs: usize, seed: u64) -> SparseDataset<f32> {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        (0..n_vecs)
+            .map(|_| {
+                let n = rng.gen_range(5..30);
+                let mut components: Vec<u16> = (0..n).map(|_| rng.gen_range(0..200)).collect();
+                components.sort();
+                components.dedup();
+                let values: Vec<f32> = components.iter().map(|_| rng.gen_range(0.1..3.0)).collect();
+                (components, values)
+            })
+            .collect()
+    }
+
+    fn small_config() -> Configuration {
+        Configuration::default()
+            .pruning_strategy(PruningStrategy::FixedSize { n_postings: 1000 })
+            .blocking_strategy(BlockingStrategy::FixedSize { block_size: 10 })
+    }
+
+    // Without pruning and with all the query components, search must be exact.
+    #[test]
+    fn test_search_matches_exhaustive_search() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
+
+        let index = InvertedIndex::build(dataset.clone(), small_config());
+
+        for (q_components, q_values) in queries.iter() {
+            let results = index.search(q_components, q_values, 10, q_components.len(), 0.0);
+            let expected = dataset.search(q_components, q_values, 10);
+
+            assert_eq!(results.len(), expected.len());
+            for (r, e) in results.iter().zip(expected.iter()) {
+                assert!((r.0 - e.0).abs() < 1e-3);
             }
+        }
+    }
 
-            let packed_posting_block = &self.packed_postings
-                [self.block_offsets[block_id]..self.block_offsets[block_id + 1]];
+    #[test]
+    fn test_search_with_compressed_forward_index() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
 
-            if blocks_to_evaluate.len() == 1 {
-                for cur_packed_posting in blocks_to_evaluate.iter() {
-                    self.evaluate_posting_block(
-                        query,
-                        query_components,
-                        query_values,
-                        cur_packed_posting,
-                        heap,
-                        visited,
-                        forward_index,
+        let config = small_config().forward_index_strategy(ForwardIndexStrategy::Compressed {
+            quantization: ValueQuantization::Bits8,
+        });
+        let index = InvertedIndex::build(dataset.clone(), config);
+
+        assert_eq!(index.len(), dataset.len());
+        assert_eq!(index.nnz(), dataset.nnz());
+
+        for (q_components, q_values) in queries.iter() {
+            let results = index.search(q_components, q_values, 10, q_components.len(), 0.0);
+            let expected = dataset.search(q_components, q_values, 10);
+
+            assert_eq!(results.len(), expected.len());
+            // Scores are approximated by the quantization of the values.
+            let max_error = 3.0 / 255.0 * q_values.iter().sum::<f32>();
+            for (r, e) in results.iter().zip(expected.iter()) {
+                assert!((r.0 - e.0).abs() <= max_error);
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_with_compressed_postings() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
+
+        let index = InvertedIndex::build(dataset.clone(), small_config());
+        let compressed_index = InvertedIndex::build(
+            dataset,
+            small_config().postings_strategy(PostingsStrategy::Compressed),
+        );
+
+        assert!(compressed_index.space_usage_byte() < index.space_usage_byte());
+
+        for (q_components, q_values) in queries.iter() {
+            let results = index.search(q_components, q_values, 10, 5, 0.7);
+            let compressed_results = compressed_index.search(q_components, q_values, 10, 5, 0.7);
+
+            let scores: Vec<_> = results.iter().map(|r| r.0).collect();
+            let compressed_scores: Vec<_> = compressed_results.iter().map(|r| r.0).collect();
+            assert_eq!(scores, compressed_scores);
+        }
+    }
+
+    #[test]
+    fn test_search_with_rerank() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
+
+        let index = InvertedIndex::build(dataset.clone().quantize_f16(), small_config())
+            .with_rerank_dataset(dataset.clone());
+
+        let config = SearchConfiguration::default()
+            .query_cut(200)
+            .heap_factor(0.0)
+            .rerank(Some(30));
+
+        for (q_components, q_values) in queries.iter() {
+            let results = index.search_with_config(q_components, q_values, 10, &config);
+            let expected = dataset.search(q_components, q_values, 10);
+
+            // Scores are exact since they are computed with the original vectors.
+            assert_eq!(results, expected);
+        }
+    }
+
+    // With an exact search, terminating early must not change the results.
+    #[test]
+    fn test_search_with_dynamic_termination() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
+
+        let index = InvertedIndex::build(dataset.clone(), small_config());
+
+        let config = SearchConfiguration::default()
+            .query_cut(200)
+            .heap_factor(0.0)
+            .dynamic_termination(true);
+
+        for (q_components, q_values) in queries.iter() {
+            let results = index.search_with_config(q_components, q_values, 10, &config);
+            let expected = dataset.search(q_components, q_values, 10);
+
+            assert_eq!(results.len(), expected.len());
+            for (r, e) in results.iter().zip(expected.iter()) {
+                assert!((r.0 - e.0).abs() < 1e-3);
+            }
+        }
+    }
+
+    // On a pruned index, terminating early must not change the results either, although a vector
+    // may be missing from the lists already visited.
+    #[test]
+    fn test_search_with_dynamic_termination_on_pruned_index() {
+        let data = vec![(vec![0], vec![1.0]), (vec![0, 1], vec![0.5, 1.1])];
+        let dataset: SparseDataset<f32> = data.into_iter().collect();
+        let config =
+            Configuration::default().pruning_strategy(PruningStrategy::FixedSize { n_postings: 1 });
+        let index = InvertedIndex::build(dataset, config);
+
+        let config = SearchConfiguration::default()
+            .heap_factor(0.0)
+            .dynamic_termination(true);
+        let results = index.search_with_config(&[0, 1], &[1.0, 0.5], 1, &config);
+        assert_eq!(results, vec![(1.05, 1)]);
+
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
+
+        for pruning in [
+            PruningStrategy::FixedSize { n_postings: 20 },
+            PruningStrategy::DocumentCentric { n_components: 5 },
+        ] {
+            let index =
+                InvertedIndex::build(dataset.clone(), small_config().pruning_strategy(pruning));
+            let config = SearchConfiguration::default()
+                .query_cut(200)
+                .heap_factor(0.0);
+
+            for (q_components, q_values) in queries.iter() {
+                let expected = index.search_with_config(q_components, q_values, 10, &config);
+                let results = index.search_with_config(
+                    q_components,
+                    q_values,
+                    10,
+                    &config.clone().dynamic_termination(true),
+                );
+
+                assert_eq!(results, expected);
+            }
+        }
+    }
+
+    // Blocks skipped by the heap factor may hold vectors not found yet, so terminating early
+    // must not change the results of the search with the same heap factor.
+    #[test]
+    fn test_search_with_heap_factor_and_dynamic_termination() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
+
+        let index = InvertedIndex::build(dataset, small_config());
+
+        for heap_factor in [0.5, 0.9] {
+            for block_scan in [BlockScan::Full, BlockScan::SortedBySummary] {
+                let config = SearchConfiguration::default()
+                    .query_cut(200)
+                    .heap_factor(heap_factor)
+                    .block_scan(block_scan);
+
+                for (q_components, q_values) in queries.iter() {
+                    let expected = index.search_with_config(q_components, q_values, 10, &config);
+                    let results = index.search_with_config(
+                        q_components,
+                        q_values,
+                        10,
+                        &config.clone().dynamic_termination(true),
                     );
+
+                    assert_eq!(results, expected);
                 }
-                blocks_to_evaluate.clear();
             }
+        }
+    }
+
+    // With query pruning, terminating early must not change the results of the pruned search,
+    // even if the components which are not selected contribute to the dot products.
+    #[test]
+    fn test_search_with_query_pruning_and_dynamic_termination() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
+
+        let index = InvertedIndex::build(dataset, small_config());
+
+        for query_pruning in [
+            QueryPruning::FixedSize { n_components: 3 },
+            QueryPruning::L1Energy { fraction: 0.5 },
+        ] {
+            let config = SearchConfiguration::default()
+                .query_pruning(query_pruning)
+                .heap_factor(0.0);
 
-            for i in (0..packed_posting_block.len()).step_by(8) {
-                prefetch_read_NTA(packed_posting_block, i);
+            for (q_components, q_values) in queries.iter() {
+                let expected = index.search_with_config(q_components, q_values, 10, &config);
+                let results = index.search_with_config(
+                    q_components,
+                    q_values,
+                    10,
+                    &config.clone().dynamic_termination(true),
+                );
+
+                assert_eq!(results, expected);
             }
+        }
+    }
 
-            blocks_to_evaluate.push(packed_posting_block);
+    // Returns the fraction of the exact top-10 results of the queries found by searching `index`
+    // with `config`.
+    fn search_recall(
+        index: &InvertedIndex<f32>,
+        dataset: &SparseDataset<f32>,
+        queries: &SparseDataset<f32>,
+        config: &SearchConfiguration,
+    ) -> f32 {
+        let mut n_found = 0;
+        for (q_components, q_values) in queries.iter() {
+            let results: HashSet<_> = index
+                .search_with_config(q_components, q_values, 10, config)
+                .into_iter()
+                .map(|(_, id)| id)
+                .collect();
+            n_found += dataset
+                .search(q_components, q_values, 10)
+                .iter()
+                .filter(|(_, id)| results.contains(id))
+                .count();
         }
 
-        for cur_packed_posting in blocks_to_evaluate.iter() {
-            self.evaluate_posting_block(
-                query,
-                query_components,
-                query_values,
-                cur_packed_posting,
-                heap,
-                visited,
-                forward_index,
+        n_found as f32 / (10 * queries.len()) as f32
+    }
+
+    // The search is exact with a zero `heap_factor`. With a larger one, blocks are skipped and
+    // some results are missed, but the recall stays high.
+    #[test]
+    fn test_search_best_first() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
+
+        let index = InvertedIndex::build(dataset.clone(), small_config());
+
+        let config = SearchConfiguration::default()
+            .query_cut(200)
+            .traversal(BlockTraversal::BestFirst);
+
+        let exact = search_recall(&index, &dataset, &queries, &config.clone().heap_factor(0.0));
+        assert_eq!(exact, 1.0);
+
+        let approximate = search_recall(&index, &dataset, &queries, &config.heap_factor(1.0));
+        assert!((0.8..1.0).contains(&approximate));
+    }
+
+    #[test]
+    fn test_search_with_sorted_blocks() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
+
+        let index = InvertedIndex::build(
+            dataset.clone(),
+            small_config().block_order(BlockOrder::MaxSummaryWeight),
+        );
+
+        for block_scan in [BlockScan::SortedBySummary, BlockScan::EarlyBreak] {
+            let config = SearchConfiguration::default()
+                .query_cut(200)
+                .block_scan(block_scan);
+
+            let exact = search_recall(&index, &dataset, &queries, &config.clone().heap_factor(0.0));
+            assert_eq!(exact, 1.0);
+
+            let approximate = search_recall(&index, &dataset, &queries, &config.heap_factor(1.0));
+            assert!((0.8..1.0).contains(&approximate));
+        }
+    }
+
+    // The blocks are in the same order for every query, so stopping at the first block below the
+    // threshold misses the better blocks which follow it, even with a zero `heap_factor` if the
+    // query has negative values.
+    #[test]
+    fn test_early_break_loses_recall() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
+        let signed_queries: SparseDataset<f32> = queries
+            .iter()
+            .map(|(components, values)| {
+                let values: Vec<_> = values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &v)| if i % 2 == 0 { -v } else { v })
+                    .collect();
+                (components.to_vec(), values)
+            })
+            .collect();
+
+        let index = InvertedIndex::build(
+            dataset.clone(),
+            small_config().block_order(BlockOrder::MaxSummaryWeight),
+        );
+
+        for (queries, heap_factor) in [(&queries, 0.8), (&signed_queries, 0.0)] {
+            let config = SearchConfiguration::default()
+                .query_cut(200)
+                .heap_factor(heap_factor);
+
+            let full = search_recall(
+                &index,
+                &dataset,
+                queries,
+                &config.clone().block_scan(BlockScan::Full),
+            );
+            let early_break = search_recall(
+                &index,
+                &dataset,
+                queries,
+                &config.block_scan(BlockScan::EarlyBreak),
             );
+            assert!(early_break < full);
         }
     }
 
-    #[allow(clippy::too_many_arguments)]
-    #[inline]
-    fn evaluate_posting_block<T>(
-        &self,
-        query: &[f32],
-        query_term_ids: &[u16],
-        query_values: &[f32],
-        packed_posting_block: &[u64],
-        heap: &mut HeapFaiss,
-        visited: &mut HashSet<usize>,
-        forward_index: &SparseDataset<T>,
-    ) where
-        T: DataType,
-    {
-        let (mut prev_offset, mut prev_len) = Self::unpack_offset_len(packed_posting_block[0]);
+    #[test]
+    fn test_pruning_strategies() {
+        let dataset = random_dataset(500, 42);
 
-        for &pack in packed_posting_block.iter().skip(1) {
-            let (offset, len) = Self::unpack_offset_len(pack);
-            forward_index.prefetch_vec_with_offset(offset, len);
+        let n_postings = |pruning: PruningStrategy| {
+            let index =
+                InvertedIndex::build(dataset.clone(), small_config().pruning_strategy(pruning));
+            index
+                .posting_lists
+                .iter()
+                .map(|list| list.block_offsets.last().copied().unwrap_or(0))
+                .sum::<usize>()
+        };
 
-            if !visited.contains(&prev_offset) {
-                let (v_components, v_values) = forward_index.get_with_offset(prev_offset, prev_len);
-                //let distance = dot_product_dense_sparse(query, v_components, v_values);
-                let distance = if query_term_ids.len() < THRESHOLD_BINARY_SEARCH {
-                    //dot_product_with_binary_search(
-                    dot_product_with_merge(query_term_ids, query_values, v_components, v_values)
-                } else {
-                    dot_product_dense_sparse(query, v_components, v_values)
+        let all = n_postings(PruningStrategy::Threshold { threshold: 0.0 });
+        assert_eq!(all, dataset.nnz());
+
+        let energy = n_postings(PruningStrategy::EnergyPreserving { fraction: 0.5 });
+        assert!(energy > 0 && energy < all);
+        assert_eq!(
+            n_postings(PruningStrategy::EnergyPreserving { fraction: 1.0 }),
+            all
+        );
+
+        let threshold = n_postings(PruningStrategy::Threshold { threshold: 2.0 });
+        let expected = dataset
+            .iter()
+            .flat_map(|(_, values)| values.iter())
+            .filter(|&&v| v >= 2.0)
+            .count();
+        assert_eq!(threshold, expected);
+
+        let document_centric = n_postings(PruningStrategy::DocumentCentric { n_components: 5 });
+        let expected: usize = dataset
+            .iter()
+            .map(|(components, _)| components.len().min(5))
+            .sum();
+        assert_eq!(document_centric, expected);
+    }
+
+    // The parallel distribution and global threshold pruning must match their sequential definitions.
+    #[test]
+    fn test_distribute_postings_and_global_threshold_pruning() {
+        let dataset = random_dataset(2000, 42);
+        // The documents are split among the threads, whatever the number of cores.
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+
+        for pruning in [
+            PruningStrategy::default(),
+            PruningStrategy::DocumentCentric { n_components: 5 },
+        ] {
+            let inverted_pairs = pool.install(|| {
+                InvertedIndex::distribute_postings(&dataset, &pruning, &SilentObserver).unwrap()
+            });
+
+            let mut expected = vec![Vec::new(); dataset.dim()];
+            for (doc_id, (components, values)) in dataset.iter().enumerate() {
+                let max_doc_components = match pruning {
+                    PruningStrategy::DocumentCentric { n_components } => n_components,
+                    _ => usize::MAX,
                 };
+                for (c, score) in
+                    InvertedIndex::document_postings(components, values, max_doc_components)
+                {
+                    expected[c as usize].push((score, doc_id));
+                }
+            }
+            assert_eq!(inverted_pairs, expected);
+        }
+
+        let inverted_pairs = InvertedIndex::distribute_postings(
+            &dataset,
+            &PruningStrategy::default(),
+            &SilentObserver,
+        )
+        .unwrap();
+        let mut all_postings: Vec<_> = inverted_pairs
+            .iter()
+            .enumerate()
+            .flat_map(|(c, list)| list.iter().map(move |&(score, doc_id)| (score, doc_id, c)))
+            .collect();
+        all_postings.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+
+        for n_postings in [0, 1, 10, 100, 1000] {
+            let mut pruned = inverted_pairs.clone();
+            let pruning = PruningStrategy::GlobalThreshold {
+                n_postings,
+                max_fraction: f32::MAX,
+            };
+            InvertedIndex::prune(&mut pruned, &pruning);
+
+            let tot_postings = (pruned.len() * n_postings).min(all_postings.len() - 1);
+            let mut expected = vec![Vec::new(); pruned.len()];
+            for &(score, doc_id, c) in &all_postings[..tot_postings] {
+                expected[c].push((score, doc_id));
+            }
+
+            assert_eq!(pruned, expected);
+        }
+    }
+
+    #[test]
+    fn test_build_with_query_log() {
+        let dataset = random_dataset(500, 42);
+        let config = small_config().pruning_strategy(PruningStrategy::FixedSize { n_postings: 20 });
+
+        // All the queries hit the first 10 components only.
+        let query_log: SparseDataset<f32> = (0..50).map(|i| (vec![i % 10], vec![1.0])).collect();
+
+        let base = InvertedIndex::build(dataset.clone(), config.clone());
+        let index = InvertedIndex::build_with_query_log(dataset.clone(), config, &query_log);
+
+        let list_len = |index: &InvertedIndex<f32>, c: usize| {
+            index.posting_lists[c]
+                .block_offsets
+                .last()
+                .copied()
+                .unwrap_or(0)
+        };
+        let tot_postings = |index: &InvertedIndex<f32>| {
+            (0..index.posting_lists.len())
+                .map(|c| list_len(index, c))
+                .sum::<usize>()
+        };
+
+        assert!(tot_postings(&index) <= tot_postings(&base));
+        for c in 0..10 {
+            assert!(list_len(&index, c) > list_len(&base, c));
+        }
+
+        // The stored pruning strategy describes the lists actually built.
+        let longest = (0..dataset.dim())
+            .map(|c| list_len(&index, c))
+            .max()
+            .unwrap();
+        match *index.config().pruning() {
+            PruningStrategy::GlobalThreshold {
+                n_postings,
+                max_fraction,
+            } => {
+                assert_eq!(n_postings, tot_postings(&index).div_ceil(dataset.dim()));
+                assert_eq!(longest, (n_postings as f32 * max_fraction).round() as usize);
+            }
+            ref pruning => panic!("Unexpected pruning strategy {pruning:?}"),
+        }
+
+        // The `max_fraction` of `GlobalThreshold` pruning bounds the length of the lists.
+        let config = small_config().pruning_strategy(PruningStrategy::GlobalThreshold {
+            n_postings: 20,
+            max_fraction: 1.5,
+        });
+        let index = InvertedIndex::build_with_query_log(dataset.clone(), config, &query_log);
+        assert!((0..dataset.dim()).all(|c| list_len(&index, c) <= 30));
+        assert_eq!(list_len(&index, 0), 30);
+
+        let queries = random_dataset(10, 7);
+        let search_config = SearchConfiguration::default()
+            .query_cut(200)
+            .heap_factor(0.0);
+        for (q_components, q_values) in queries.iter() {
+            let results = index.search_with_config(q_components, q_values, 10, &search_config);
+            assert_eq!(results.len(), 10);
+        }
+    }
 
-                visited.insert(prev_offset);
-                heap.push_with_id(-1.0 * distance, prev_offset);
-            }
+    // Cancels the construction once some posting lists have been built.
+    #[derive(Default)]
+    struct CancelDuringLists(std::sync::atomic::AtomicUsize);
 
-            prev_offset = offset;
-            prev_len = len;
+    impl BuildObserver for CancelDuringLists {
+        fn progress(&self, phase: BuildPhase, n: usize) {
+            if phase == BuildPhase::BuildingPostingLists {
+                self.0.fetch_add(n, std::sync::atomic::Ordering::Relaxed);
+            }
         }
 
-        if visited.contains(&prev_offset) {
-            return;
+        fn is_cancelled(&self) -> bool {
+            self.0.load(std::sync::atomic::Ordering::Relaxed) >= 10
         }
+    }
 
-        let (v_components, v_values) = forward_index.get_with_offset(prev_offset, prev_len);
-        let distance = if query_term_ids.len() < THRESHOLD_BINARY_SEARCH {
-            //dot_product_with_binary_search(
-            dot_product_with_merge(query_term_ids, query_values, v_components, v_values)
-        } else {
-            dot_product_dense_sparse(query, v_components, v_values)
-        };
+    #[test]
+    fn test_build_cancelled() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
 
-        visited.insert(prev_offset);
-        heap.push_with_id(-1.0 * distance, prev_offset);
-    }
+        let result = InvertedIndex::build_with_observer(
+            dataset.clone(),
+            small_config(),
+            &CancelDuringLists::default(),
+        );
+        assert_eq!(result.unwrap_err(), BuildCancelled);
 
-    /// Gets a posting list already pruned and represents it by using a blocking
-    /// strategy to partition postings into block and a summarization strategy to
-    /// represents the summary of each block.
-    pub fn build<T>(
-        dataset: &SparseDataset<T>,
-        postings: &[(T, usize)],
-        config: &Configuration,
-    ) -> Self
-    where
-        T: PartialOrd + DataType,
-    {
-        let mut posting_list: Vec<_> = postings.iter().map(|(_, docid)| *docid).collect();
+        let result = InvertedIndex::build_with_query_log_with_observer(
+            dataset.clone(),
+            small_config(),
+            &queries,
+            &CancelDuringLists::default(),
+        );
+        assert_eq!(result.unwrap_err(), BuildCancelled);
 
-        let block_offsets = match config.blocking {
-            BlockingStrategy::FixedSize { block_size } => {
-                Self::fixed_size_blocking(&posting_list, block_size)
-            }
+        let result = InvertedIndex::build_with_budget_with_observer(
+            dataset.clone(),
+            small_config(),
+            dataset.space_usage_byte() * 2,
+            &CancelDuringLists::default(),
+        );
+        assert_eq!(result.unwrap_err(), BuildCancelled);
+    }
 
-            BlockingStrategy::RandomKmeans {
-                centroid_fraction,
-                truncated_kmeans_training,
-                truncation_size,
-                min_cluster_size,
-            } => Self::blocking_with_random_kmeans(
-                &mut posting_list,
-                centroid_fraction,
-                truncated_kmeans_training,
-                truncation_size,
-                min_cluster_size,
-                dataset,
-            ),
-        };
+    #[test]
+    fn test_build_with_budget() {
+        let dataset = random_dataset(500, 42);
 
-        let mut summaries = SparseDatasetMut::<T>::new();
+        // A dataset without components has no posting lists.
+        let empty = SparseDataset::<f32>::default();
+        let config = small_config().pruning_strategy(PruningStrategy::GlobalThreshold {
+            n_postings: 1000,
+            max_fraction: 1.5,
+        });
+        let index = InvertedIndex::build_with_budget(empty, config, 1 << 20);
+        assert_eq!(index.stats().n_posting_lists, 0);
 
-        for block_range in block_offsets.windows(2) {
-            let (components, values) = match config.summarization {
-                SummarizationStrategy::FixedSize { n_components } => Self::fixed_size_summary(
-                    dataset,
-                    &posting_list[block_range[0]..block_range[1]],
-                    n_components,
-                ),
+        for pruning in [
+            PruningStrategy::FixedSize { n_postings: 1000 },
+            PruningStrategy::GlobalThreshold {
+                n_postings: 1000,
+                max_fraction: 1.5,
+            },
+            PruningStrategy::Threshold { threshold: 0.0 },
+        ] {
+            let config = small_config().pruning_strategy(pruning.clone());
+            let full_size =
+                InvertedIndex::build(dataset.clone(), config.clone()).space_usage_byte();
+            let budget = dataset.space_usage_byte() + (full_size - dataset.space_usage_byte()) / 2;
 
-                SummarizationStrategy::EnergyPerserving {
-                    summary_energy: fraction,
-                } => Self::energy_preserving_summary(
-                    dataset,
-                    &posting_list[block_range[0]..block_range[1]],
-                    fraction,
-                ),
-            };
+            let index = InvertedIndex::build_with_budget(dataset.clone(), config.clone(), budget);
+            assert!(index.space_usage_byte() <= budget);
+            // Most of the budget is used.
+            let lists_budget = budget - dataset.space_usage_byte();
+            let lists_size = index.space_usage_byte() - dataset.space_usage_byte();
+            assert!(lists_size as f32 > 0.85 * lists_budget as f32);
 
-            summaries.push(&components, &values);
-        }
+            // Shortened lists are described by their average length.
+            if let PruningStrategy::Threshold { .. } = pruning {
+                let stats = index.stats();
+                assert_eq!(
+                    index.config().pruning(),
+                    &PruningStrategy::GlobalThreshold {
+                        n_postings: stats.n_postings.div_ceil(stats.n_posting_lists),
+                        max_fraction: stats.posting_list_lengths.max as f32
+                            / stats.n_postings.div_ceil(stats.n_posting_lists) as f32,
+                    }
+                );
+            }
 
-        let packed_postings: Vec<_> = posting_list
-            .iter()
-            .map(|doc_id| {
-                Self::pack_offset_len(dataset.vector_offset(*doc_id), dataset.vector_len(*doc_id))
-            })
-            .collect();
+            // A tight budget is never exceeded.
+            let empty_size = InvertedIndex::build(
+                dataset.clone(),
+                small_config().pruning_strategy(PruningStrategy::FixedSize { n_postings: 0 }),
+            )
+            .space_usage_byte();
+            let budget = empty_size + (full_size - empty_size) / 20;
+            let index = InvertedIndex::build_with_budget(dataset.clone(), config.clone(), budget);
+            assert!(index.space_usage_byte() <= budget);
 
-        Self {
-            packed_postings: packed_postings.into_boxed_slice(),
-            block_offsets: block_offsets.into_boxed_slice(),
-            summaries: QuantizedSummary::new(
-                SparseDataset::<T>::from(summaries).quantize_f16(),
-                dataset.dim(),
-            ),
+            // A budget which only fits empty posting lists gives an index without postings.
+            let index = InvertedIndex::build_with_budget(dataset.clone(), config, empty_size);
+            assert!(index.space_usage_byte() <= empty_size);
+            assert_eq!(index.stats().n_postings, 0);
         }
     }
 
-    // ** Blocking strategies **
-
-    fn fixed_size_blocking(posting_list: &[usize], block_size: usize) -> Vec<usize> {
-        // of course this strategy would not need offsets, but we are using them
-        // just to have just one, "universal" query search implementation
-        let mut block_offsets: Vec<_> = (0..posting_list.len() / block_size)
-            .map(|i| i * block_size)
-            .collect();
+    #[test]
+    #[should_panic(expected = "without postings")]
+    fn test_build_with_too_small_budget() {
+        let dataset = random_dataset(500, 42);
+        let config = small_config().pruning_strategy(PruningStrategy::GlobalThreshold {
+            n_postings: 1000,
+            max_fraction: 1.5,
+        });
+        let empty_size = InvertedIndex::build(
+            dataset.clone(),
+            small_config().pruning_strategy(PruningStrategy::FixedSize { n_postings: 0 }),
+        )
+        .space_usage_byte();
 
-        block_offsets.push(posting_list.len());
-        block_offsets
+        InvertedIndex::build_with_budget(dataset, config, empty_size - 1);
     }
 
-    fn blocking_with_random_kmeans<T: DataType>(
-        posting_list: &mut [usize],
-        centroid_fraction: f32,
-        truncated_kmeans_training: bool,
-        _truncation_size: usize,
-        min_cluster_size: usize,
-        dataset: &SparseDataset<T>,
-    ) -> Vec<usize> {
-        if posting_list.is_empty() {
-            return Vec::new();
-        }
+    #[test]
+    fn test_search_range() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
 
-        let n_centroids = ((centroid_fraction * posting_list.len() as f32) as usize).max(1);
-        let mut reordered_posting_list = Vec::<_>::with_capacity(posting_list.len());
-        let mut block_offsets = Vec::with_capacity(n_centroids);
+        // Summaries with all the components of their blocks overestimate the dot products,
+        // so no result is missed.
+        let index = InvertedIndex::build(
+            dataset.clone(),
+            small_config().summarization_strategy(SummarizationStrategy::EnergyPerserving {
+                summary_energy: 1.0,
+            }),
+        );
 
-        if truncated_kmeans_training {
-            // Need to change only how clustering results is computed
-            todo!();
-        } else {
-            let clustering_results =
-                do_random_kmeans_on_docids(posting_list, n_centroids, dataset, min_cluster_size);
+        for (q_components, q_values) in queries.iter() {
+            let threshold = 10.0;
+            let results = index.search_range(q_components, q_values, threshold, None);
 
-            block_offsets.push(0);
+            let expected = dataset.search(q_components, q_values, dataset.len());
+            let expected: Vec<_> = expected.into_iter().filter(|r| r.0 >= threshold).collect();
 
-            for cluster in clustering_results {
-                if cluster.is_empty() {
-                    continue;
-                }
-                reordered_posting_list.extend(cluster);
-                block_offsets.push(reordered_posting_list.len());
+            assert_eq!(results.len(), expected.len());
+            for (r, e) in results.iter().zip(expected.iter()) {
+                assert!((r.0 - e.0).abs() < 1e-3);
             }
 
-            assert_eq!(reordered_posting_list.len(), posting_list.len());
-            posting_list.copy_from_slice(&reordered_posting_list);
+            let capped = index.search_range(q_components, q_values, threshold, Some(3));
+            assert_eq!(capped.len(), results.len().min(3));
         }
-
-        block_offsets
     }
 
-    // ** Summarization strategies **
+    #[test]
+    fn test_search_grouped_by_document() {
+        let dataset = random_dataset(500, 42);
+        let queries = random_dataset(20, 7);
 
-    fn fixed_size_summary<T>(
-        dataset: &SparseDataset<T>,
-        block: &[usize],
-        n_components: usize,
-    ) -> (Vec<u16>, Vec<T>)
-    where
-        T: PartialOrd + DataType,
-    {
-        let mut hash = HashMap::new();
-        for &doc_id in block.iter() {
-            // for each component_id, store the largest value seen so far
-            for (&c, &v) in dataset.iter_vector(doc_id) {
-                hash.entry(c)
-                    .and_modify(|h| *h = if *h < v { v } else { *h })
-                    .or_insert(v);
+        // Every document has 5 consecutive passages.
+        let passage_documents: Vec<_> = (0..dataset.len()).map(|id| id / 5).collect();
+        let index = InvertedIndex::build(dataset.clone(), small_config())
+            .with_passage_documents(passage_documents.clone());
+
+        for aggregation in [
+            DocumentAggregation::MaxPassage,
+            DocumentAggregation::SumTopPassages { m: 2 },
+        ] {
+            let m = match aggregation {
+                DocumentAggregation::MaxPassage => 1,
+                DocumentAggregation::SumTopPassages { m } => m,
+            };
+            let config = SearchConfiguration::default()
+                .query_cut(200)
+                .heap_factor(0.0)
+                .document_aggregation(Some(aggregation));
+
+            let (q_components, q_values) = queries.get(0);
+            assert!(index
+                .search_with_config(q_components, q_values, 0, &config)
+                .is_empty());
+
+            for (q_components, q_values) in queries.iter() {
+                let results = index.search_with_config(q_components, q_values, 10, &config);
+
+                let mut document_scores = HashMap::<usize, Vec<f32>>::new();
+                for (score, id) in dataset.search(q_components, q_values, dataset.len()) {
+                    if score > 0.0 {
+                        document_scores
+                            .entry(passage_documents[id])
+                            .or_default()
+                            .push(score);
+                    }
+                }
+                let mut expected: Vec<_> = document_scores
+                    .values()
+                    .map(|scores| scores.iter().take(m).sum::<f32>())
+                    .collect();
+                expected.sort_by(|a, b| b.total_cmp(a));
+                expected.truncate(10);
+
+                // Terminating early does not change the documents.
+                let terminated = index.search_with_config(
+                    q_components,
+                    q_values,
+                    10,
+                    &config.clone().dynamic_termination(true),
+                );
+                assert_eq!(terminated, results);
+
+                let ids: HashSet<_> = results.iter().map(|r| r.1).collect();
+                assert_eq!(ids.len(), results.len());
+                assert_eq!(results.len(), expected.len());
+                for (r, e) in results.iter().zip(expected.iter()) {
+                    assert!((r.0 - e).abs() < 1e-3);
+                }
             }
         }
+    }
 
-        let mut components_values: Vec<_> = hash.iter().collect();
+    // The statistics must account for every posting, block, and byte of the index.
+    #[test]
+    fn test_stats() {
+        let dataset = random_dataset(300, 3);
+        let index = InvertedIndex::build(dataset, small_config())
+            .with_passage_documents((0..300).map(|i| i / 3).collect());
 
-        // First sort by decreasing scores, then take only up to LIMIT and sort by component_id
-        components_values.sort_unstable_by(|a, b| b.1.partial_cmp(a.1).unwrap());
+        let stats = index.stats();
 
-        components_values.truncate(n_components);
+        assert_eq!(stats.n_documents, 300);
+        assert_eq!(stats.n_posting_lists, index.posting_lists.len());
+        assert_eq!(
+            stats.posting_list_lengths.count + stats.n_empty_posting_lists,
+            stats.n_posting_lists
+        );
+        // Without pruning, every component of every document is a posting.
+        assert_eq!(stats.n_postings, stats.nnz);
+        assert_eq!(
+            stats.posting_list_lengths.mean * stats.posting_list_lengths.count as f64,
+            stats.n_postings as f64
+        );
 
-        components_values.sort_unstable_by(|a, b| a.0.cmp(b.0)); // sort by id to make binary search possible
+        assert_eq!(stats.block_sizes.count, stats.n_blocks);
+        assert_eq!(stats.summary_lengths.count, stats.n_blocks);
+        assert_eq!(stats.summary_quantization.n_summaries, stats.n_blocks);
+        assert!(stats.summary_quantization.min_value <= stats.summary_quantization.max_value);
 
-        let components: Vec<_> = components_values
-            .iter()
-            .map(|(&component_id, _score)| component_id)
-            .collect();
+        let space = &stats.space_usage;
+        assert_eq!(
+            space.forward_index
+                + space.postings
+                + space.block_offsets
+                + space.summaries
+                + space.max_values
+                + space.rerank_dataset
+                + space.passage_documents,
+            space.total
+        );
+        assert_eq!(space.total, index.space_usage_byte());
+        assert_eq!(&stats.configuration, index.config());
+    }
 
-        let values: Vec<_> = components.iter().copied().map(|k| hash[&k]).collect();
+    // An empty list has no blocks, a list shorter than the block size has a single block,
+    // and the last block takes the remaining postings.
+    #[test]
+    fn test_fixed_size_blocking() {
+        let blocking =
+            |len: usize| PostingList::fixed_size_blocking(&(0..len).collect::<Vec<_>>(), 4);
 
-        (components, values)
+        assert_eq!(blocking(0), Vec::<usize>::new());
+        assert_eq!(blocking(3), vec![0, 3]);
+        assert_eq!(blocking(8), vec![0, 4, 8]);
+        assert_eq!(blocking(10), vec![0, 4, 10]);
     }
 
-    fn energy_preserving_summary<T>(
-        dataset: &SparseDataset<T>,
-        block: &[usize],
-        fraction: f32,
-    ) -> (Vec<u16>, Vec<T>)
-    where
-        T: PartialOrd + DataType,
-    {
-        let mut hash = HashMap::new();
-        for &doc_id in block.iter() {
-            // for each component_id, store the largest value seen so far
-            for (&c, &v) in dataset.iter_vector(doc_id) {
-                hash.entry(c)
-                    .and_modify(|h| *h = if *h < v { v } else { *h })
-                    .or_insert(v);
-            }
-        }
+    // Corruptions of the blocks and of the postings are reported with their position.
+    #[test]
+    fn test_validate() {
+        let dataset = random_dataset(300, 5);
+        let index = InvertedIndex::build(dataset.clone(), small_config());
+        assert_eq!(index.validate(), Ok(()));
 
-        let mut components_values: Vec<_> = hash.iter().collect();
+        let list = (0..index.posting_lists.len())
+            .find(|&list| index.posting_lists[list].n_blocks() >= 2)
+            .unwrap();
 
-        components_values.sort_unstable_by(|a, b| b.1.partial_cmp(a.1).unwrap());
-        let total_sum = components_values
-            .iter()
-            .fold(0_f32, |sum, (_, &x)| sum + x.to_f32().unwrap());
+        let mut corrupted = index.clone();
+        let posting_list = &mut corrupted.posting_lists[list];
+        let end = posting_list.block_offsets[2];
+        posting_list.block_offsets[1] = end;
+        let Postings::Packed(packed_postings) = &mut posting_list.postings else {
+            unreachable!()
+        };
+        let (offset, len) = PostingList::unpack_offset_len(packed_postings[0]);
+        packed_postings[0] = PostingList::pack_offset_len(offset, len + 1);
+        packed_postings[1] = PostingList::pack_offset_len(offset + 1, len);
 
-        let mut term_ids = Vec::new();
-        let mut values = Vec::new();
-        let mut acc = 0_f32;
-        for (&tid, &v) in components_values.iter() {
-            acc += v.to_f32().unwrap();
-            term_ids.push(tid);
-            values.push(v);
-            if (acc / total_sum) > fraction {
-                break;
-            }
-        }
-        term_ids.sort();
-        let values: Vec<T> = term_ids.iter().copied().map(|k| hash[&k]).collect();
-        (term_ids, values)
-    }
-}
+        let doc_id = index.forward_index.offset_to_id(offset);
+        assert_eq!(
+            corrupted.validate(),
+            Err(vec![
+                Violation::BlockOffsetsNotIncreasing {
+                    list,
+                    block: 1,
+                    start: end,
+                    end,
+                },
+                Violation::PostingLength {
+                    list,
+                    position: 0,
+                    doc_id,
+                    len: len + 1,
+                    vector_len: len,
+                },
+                Violation::PostingOffset {
+                    list,
+                    position: 1,
+                    offset: offset + 1,
+                },
+            ])
+        );
 
-#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
-/// Represents the possible choices for the strategy used to prune the posting
-/// lists at building time.
-/// There are the following possible strategies:
-/// - `Fixed  { n_postings: usize }`: Every posting list is pruned by taking its top-`n_postings`
-/// - `GlobalThreshold { n_postings: usize, max_fraction: f32 }`: We globally select a threshold and we prune all the postings with smaller score. The threshold is chosen so that every posting list has `n_postings` on average. We limit the number of postings per list to `max_fraction*n_postings`.
-pub enum PruningStrategy {
-    FixedSize {
-        n_postings: usize,
-    },
-    GlobalThreshold {
-        n_postings: usize,
-        max_fraction: f32, // limits the length of each posting list to max_fraction*n_postings
-    },
-}
+        let index = InvertedIndex::build(
+            dataset,
+            small_config().postings_strategy(PostingsStrategy::Compressed),
+        );
+        assert_eq!(index.validate(), Ok(()));
 
-impl Default for PruningStrategy {
-    fn default() -> Self {
-        Self::FixedSize { n_postings: 3500 }
+        let mut corrupted = index.clone();
+        let Postings::Compressed { data, .. } = &mut corrupted.posting_lists[list].postings else {
+            unreachable!()
+        };
+        let mut truncated = data.to_vec();
+        truncated.pop();
+        *data = truncated.into_boxed_slice();
+
+        let n_blocks = index.posting_lists[list].n_blocks();
+        assert_eq!(
+            corrupted.validate(),
+            Err(vec![Violation::CompressedBlock {
+                list,
+                block: n_blocks - 1
+            }])
+        );
     }
-}
 
-#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
-pub enum BlockingStrategy {
-    FixedSize {
-        block_size: usize,
-    },
+    // With fixed-size pruning and blocking, merging gives the same index as building it from scratch.
+    #[test]
+    fn test_merge() {
+        let dataset_a = random_dataset(200, 11);
+        // The second dataset has a smaller dimensionality.
+        let dataset_b: SparseDataset<f32> = random_dataset(150, 12)
+            .iter()
+            .map(|(components, values)| {
+                let n = components.partition_point(|&c| c < 120);
+                (&components[..n], &values[..n])
+            })
+            .collect();
+        let dataset: SparseDataset<f32> = dataset_a.iter().chain(dataset_b.iter()).collect();
 
-    RandomKmeans {
-        centroid_fraction: f32,
-        truncated_kmeans_training: bool,
-        truncation_size: usize,
-        min_cluster_size: usize,
-    },
-}
+        let config = small_config().pruning_strategy(PruningStrategy::FixedSize { n_postings: 15 });
+        for config in [
+            config.clone(),
+            config.postings_strategy(PostingsStrategy::Compressed),
+        ] {
+            let a = InvertedIndex::build(dataset_a.clone(), config.clone());
+            let b = InvertedIndex::build(dataset_b.clone(), config.clone());
 
-impl Default for BlockingStrategy {
-    fn default() -> Self {
-        BlockingStrategy::RandomKmeans {
-            centroid_fraction: 0.1,
-            truncated_kmeans_training: false,
-            truncation_size: 32,
-            min_cluster_size: 2,
+            let merged = InvertedIndex::merge(a.clone(), b.clone()).unwrap();
+            assert_eq!(merged.validate(), Ok(()));
+            assert_eq!(merged, InvertedIndex::build(dataset.clone(), config));
+
+            // The data of the passages cannot be dropped from one of the indexes.
+            let with_rerank = a.clone().with_rerank_dataset(dataset_a.clone());
+            assert_eq!(
+                InvertedIndex::merge(with_rerank, b.clone()),
+                Err(MergeError::RerankDatasetMismatch)
+            );
+            let with_documents = b.with_passage_documents(vec![0; dataset_b.len()]);
+            assert_eq!(
+                InvertedIndex::merge(a, with_documents),
+                Err(MergeError::PassageDocumentsMismatch)
+            );
         }
+
+        let a = InvertedIndex::build(dataset_a, small_config());
+        let b = InvertedIndex::build(
+            dataset_b,
+            small_config().block_order(BlockOrder::MaxSummaryWeight),
+        );
+        assert_eq!(
+            InvertedIndex::merge(a, b),
+            Err(MergeError::DifferentConfigurations)
+        );
     }
-}
 
-#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
-pub enum SummarizationStrategy {
-    FixedSize { n_components: usize },
-    EnergyPerserving { summary_energy: f32 },
-}
+    // Resummarizing and repruning an index give the same index as building it from scratch.
+    #[test]
+    fn test_resummarize_and_reprune() {
+        let dataset = random_dataset(300, 13);
+        let summarization =
+            |summary_energy| SummarizationStrategy::EnergyPerserving { summary_energy };
+        let config = small_config().summarization_strategy(summarization(0.4));
 
-impl Default for SummarizationStrategy {
-    fn default() -> Self {
-        Self::EnergyPerserving {
-            summary_energy: 0.4,
+        let index = InvertedIndex::build(dataset.clone(), config.clone());
+        let expected = InvertedIndex::build(
+            dataset.clone(),
+            config.clone().summarization_strategy(summarization(0.8)),
+        );
+        assert_eq!(index.clone().resummarize(summarization(0.8)), expected);
+
+        for (pruning, repruning) in [
+            (
+                PruningStrategy::FixedSize { n_postings: 1000 },
+                PruningStrategy::FixedSize { n_postings: 20 },
+            ),
+            (
+                PruningStrategy::DocumentCentric { n_components: 10 },
+                PruningStrategy::DocumentCentric { n_components: 5 },
+            ),
+        ] {
+            let index = InvertedIndex::build(
+                dataset.clone(),
+                config.clone().pruning_strategy(pruning.clone()),
+            );
+            let expected = InvertedIndex::build(
+                dataset.clone(),
+                config.clone().pruning_strategy(repruning.clone()),
+            );
+            assert_eq!(index.clone().reprune(repruning).unwrap(), expected);
+
+            // The postings pruned away cannot be recovered.
+            let looser = match pruning {
+                PruningStrategy::FixedSize { n_postings } => PruningStrategy::FixedSize {
+                    n_postings: n_postings + 1,
+                },
+                _ => PruningStrategy::Threshold { threshold: 0.0 },
+            };
+            let error = index.reprune(looser.clone()).unwrap_err();
+            assert_eq!(error.pruning, looser);
+            assert_eq!(error.index.config().pruning(), &pruning);
         }
     }
+
+    // With a compressed forward index, the vectors decoded list by list give the same lists
+    // as the whole decompressed forward index.
+    #[test]
+    fn test_resummarize_and_reprune_compressed_forward_index() {
+        let dataset = random_dataset(300, 14);
+        let config = small_config()
+            .pruning_strategy(PruningStrategy::DocumentCentric { n_components: 10 })
+            .forward_index_strategy(ForwardIndexStrategy::Compressed {
+                quantization: ValueQuantization::Bits8,
+            })
+            .postings_strategy(PostingsStrategy::Compressed);
+        let index = InvertedIndex::build(dataset, config.clone());
+
+        // The postings store doc ids, so the lists are valid on the decompressed vectors too.
+        let decompressed = match &index.forward_index {
+            ForwardIndex::Compressed(dataset) => InvertedIndex {
+                forward_index: ForwardIndex::Plain(dataset.decompress()),
+                config: config
+                    .clone()
+                    .forward_index_strategy(ForwardIndexStrategy::Uncompressed),
+                ..index.clone()
+            },
+            ForwardIndex::Plain(_) => unreachable!(),
+        };
+
+        // Summaries with all the components do not depend on the order of ties among values.
+        let summarization = SummarizationStrategy::FixedSize {
+            n_components: usize::MAX,
+        };
+        let index = index.resummarize(summarization.clone());
+        let decompressed = decompressed.resummarize(summarization);
+        assert_eq!(index.posting_lists, decompressed.posting_lists);
+
+        let pruning = PruningStrategy::DocumentCentric { n_components: 5 };
+        let repruned = index.reprune(pruning.clone()).unwrap();
+        assert_eq!(repruned.validate(), Ok(()));
+        assert_eq!(
+            repruned.posting_lists,
+            decompressed.reprune(pruning).unwrap().posting_lists
+        );
+    }
 }
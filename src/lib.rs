@@ -7,6 +7,8 @@
 
 use half::f16;
 
+// pyo3 0.19 expands `#[new]` into impls nested in functions.
+#[allow(non_local_definitions)]
 pub mod pylib;
 
 pub mod sparse_dataset;
@@ -14,10 +16,30 @@ pub mod sparse_dataset;
 pub use sparse_dataset::SparseDataset;
 pub use sparse_dataset::SparseDatasetMut;
 
+pub mod build_observer;
+
+pub use build_observer::BuildObserver;
+
 pub mod inverted_index;
 
 pub use inverted_index::InvertedIndex;
 
+pub mod index_handle;
+
+pub mod index_stats;
+
+pub use index_stats::IndexStats;
+
+pub use index_handle::IndexHandle;
+
+pub mod validation;
+
+pub use validation::Violation;
+
+pub mod compressed_dataset;
+
+pub use compressed_dataset::CompressedSparseDataset;
+
 pub mod quantized_summary;
 
 pub use quantized_summary::QuantizedSummary;
@@ -30,7 +52,11 @@ pub mod distances;
 pub mod topk_selectors;
 pub mod utils;
 
-use crate::pylib::PySeismicIndex;
+use crate::pylib::{
+    PyBlockingStrategy, PyConfiguration, PyDocumentAggregation, PyIndexHandle, PyPruningStrategy,
+    PyQueryPruning, PyReloadTask, PySeismicIndex, PySeismicIndexBuilder, PySparseDataset,
+    PySummarizationStrategy,
+};
 use num_traits::{AsPrimitive, ToPrimitive, Zero};
 use pyo3::prelude::PyModule;
 use pyo3::{pymodule, PyResult, Python};
@@ -52,5 +78,15 @@ impl DataType for f16 {}
 #[pymodule]
 fn seismic(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_class::<PySeismicIndex>()?;
+    m.add_class::<PySeismicIndexBuilder>()?;
+    m.add_class::<PySparseDataset>()?;
+    m.add_class::<PyQueryPruning>()?;
+    m.add_class::<PyPruningStrategy>()?;
+    m.add_class::<PyBlockingStrategy>()?;
+    m.add_class::<PySummarizationStrategy>()?;
+    m.add_class::<PyConfiguration>()?;
+    m.add_class::<PyDocumentAggregation>()?;
+    m.add_class::<PyIndexHandle>()?;
+    m.add_class::<PyReloadTask>()?;
     Ok(())
 }
@@ -1,116 +1,1539 @@
-use crate::{InvertedIndex, SparseDataset};
+use crate::build_observer::BuildPhase;
+use crate::compressed_dataset::ValueQuantization;
+use crate::inverted_index::{
+    BlockOrder, BlockScan, BlockTraversal, BlockingStrategy, Configuration, DocumentAggregation,
+    ForwardIndexStrategy, PostingsStrategy, PruningStrategy, QueryPruning, SearchConfiguration,
+    SummarizationStrategy,
+};
+use crate::{BuildObserver, DataType, IndexHandle, InvertedIndex, SparseDataset, SparseDatasetMut};
 use half::f16;
-use numpy::PyReadonlyArrayDyn;
+use num_traits::AsPrimitive;
+use numpy::ndarray::{Array2, ArrayView1};
+use numpy::{Element, IntoPyArray, PyArray1, PyArray2, PyReadonlyArray1, PyReadonlyArrayDyn};
+use pyo3::exceptions::{PyIOError, PyIndexError, PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyBytes, PyTuple};
 use rayon::prelude::*;
-use std::fs;
-use crate::inverted_index::{BlockingStrategy, Configuration, PruningStrategy, SummarizationStrategy};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
 
-#[pyclass]
-pub struct PySeismicIndex {
-    inverted_index: InvertedIndex<f16>,
+/// The strategy used to select the components of a query whose posting lists are evaluated.
+/// If given to a search, it replaces `query_cut`.
+#[pyclass(name = "QueryPruning")]
+#[derive(Clone)]
+pub struct PyQueryPruning {
+    query_pruning: QueryPruning,
 }
 
 #[pymethods]
-impl PySeismicIndex {
+impl PyQueryPruning {
     #[staticmethod]
-    pub fn load(index_path: &str) -> PyResult<PySeismicIndex> {
-        let serialized: Vec<u8> = fs::read(index_path).unwrap();
-        let inverted_index = bincode::deserialize::<InvertedIndex<f16>>(&serialized).unwrap();
-        Ok(PySeismicIndex { inverted_index })
+    pub fn fixed_size(n_components: usize) -> Self {
+        Self {
+            query_pruning: QueryPruning::FixedSize { n_components },
+        }
     }
 
-    pub fn save(&self, path: &str) {
-        let serialized = bincode::serialize(&self.inverted_index).unwrap();
-        let path = path.to_string() + "index.seismic";
-        println!("Saving ... {}", path);
-        let r = fs::write(path, serialized);
-        println!("{:?}", r);
+    #[staticmethod]
+    pub fn l1_energy(fraction: f32) -> Self {
+        Self {
+            query_pruning: QueryPruning::L1Energy { fraction },
+        }
     }
 
     #[staticmethod]
-    #[allow(clippy::too_many_arguments)]
-    pub fn build(input_file: &str,
-                 n_postings: usize,
-                 centroid_fraction: f32,
-                 truncated_kmeans_training: bool,
-                 truncation_size: usize,
-                 min_cluster_size: usize,
-                 summary_energy: f32) -> PyResult<PySeismicIndex> {
-        let dataset = SparseDataset::<f32>::read_bin_file(input_file)
-            .unwrap()
-            .quantize_f16();
-
-        let config = Configuration::default()
-            .pruning_strategy(PruningStrategy::GlobalThreshold {
+    pub fn l2_energy(fraction: f32) -> Self {
+        Self {
+            query_pruning: QueryPruning::L2Energy { fraction },
+        }
+    }
+
+    #[staticmethod]
+    pub fn min_value(threshold: f32) -> Self {
+        Self {
+            query_pruning: QueryPruning::MinValue { threshold },
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.query_pruning)
+    }
+}
+
+/// The strategy used to prune the posting lists when building an index.
+/// If given to `build`, it replaces the global threshold pruning with `n_postings` and `max_fraction`.
+#[pyclass(name = "PruningStrategy")]
+#[derive(Clone)]
+pub struct PyPruningStrategy {
+    pruning: PruningStrategy,
+}
+
+#[pymethods]
+impl PyPruningStrategy {
+    #[staticmethod]
+    pub fn fixed_size(n_postings: usize) -> Self {
+        Self {
+            pruning: PruningStrategy::FixedSize { n_postings },
+        }
+    }
+
+    #[staticmethod]
+    pub fn global_threshold(n_postings: usize, max_fraction: f32) -> Self {
+        Self {
+            pruning: PruningStrategy::GlobalThreshold {
                 n_postings,
-                max_fraction: 1.5,
-            })
-            .blocking_strategy(BlockingStrategy::RandomKmeans {
+                max_fraction,
+            },
+        }
+    }
+
+    #[staticmethod]
+    pub fn energy_preserving(fraction: f32) -> Self {
+        Self {
+            pruning: PruningStrategy::EnergyPreserving { fraction },
+        }
+    }
+
+    #[staticmethod]
+    pub fn threshold(threshold: f32) -> Self {
+        Self {
+            pruning: PruningStrategy::Threshold { threshold },
+        }
+    }
+
+    #[staticmethod]
+    pub fn document_centric(n_components: usize) -> Self {
+        Self {
+            pruning: PruningStrategy::DocumentCentric { n_components },
+        }
+    }
+
+    /// The name of the strategy, as the static method creating it, e.g., `"global_threshold"`.
+    #[getter]
+    pub fn kind(&self) -> &'static str {
+        match self.pruning {
+            PruningStrategy::FixedSize { .. } => "fixed_size",
+            PruningStrategy::GlobalThreshold { .. } => "global_threshold",
+            PruningStrategy::EnergyPreserving { .. } => "energy_preserving",
+            PruningStrategy::Threshold { .. } => "threshold",
+            PruningStrategy::DocumentCentric { .. } => "document_centric",
+        }
+    }
+
+    /// The parameters below are `None` if the strategy does not have them.
+    #[getter]
+    pub fn n_postings(&self) -> Option<usize> {
+        match self.pruning {
+            PruningStrategy::FixedSize { n_postings }
+            | PruningStrategy::GlobalThreshold { n_postings, .. } => Some(n_postings),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    pub fn max_fraction(&self) -> Option<f32> {
+        match self.pruning {
+            PruningStrategy::GlobalThreshold { max_fraction, .. } => Some(max_fraction),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    pub fn fraction(&self) -> Option<f32> {
+        match self.pruning {
+            PruningStrategy::EnergyPreserving { fraction } => Some(fraction),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    pub fn threshold_value(&self) -> Option<f32> {
+        match self.pruning {
+            PruningStrategy::Threshold { threshold } => Some(threshold),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    pub fn n_components(&self) -> Option<usize> {
+        match self.pruning {
+            PruningStrategy::DocumentCentric { n_components } => Some(n_components),
+            _ => None,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.pruning)
+    }
+}
+
+/// The strategy used to split each posting list into blocks when building an index.
+#[pyclass(name = "BlockingStrategy")]
+#[derive(Clone)]
+pub struct PyBlockingStrategy {
+    blocking: BlockingStrategy,
+}
+
+#[pymethods]
+impl PyBlockingStrategy {
+    #[staticmethod]
+    pub fn fixed_size(block_size: usize) -> Self {
+        Self {
+            blocking: BlockingStrategy::FixedSize { block_size },
+        }
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (centroid_fraction, truncated_kmeans_training=false, truncation_size=16, min_cluster_size=2))]
+    pub fn random_kmeans(
+        centroid_fraction: f32,
+        truncated_kmeans_training: bool,
+        truncation_size: usize,
+        min_cluster_size: usize,
+    ) -> Self {
+        Self {
+            blocking: BlockingStrategy::RandomKmeans {
                 centroid_fraction,
                 truncated_kmeans_training,
                 truncation_size,
                 min_cluster_size,
+            },
+        }
+    }
+
+    /// The name of the strategy, as the static method creating it, e.g., `"random_kmeans"`.
+    #[getter]
+    pub fn kind(&self) -> &'static str {
+        match self.blocking {
+            BlockingStrategy::FixedSize { .. } => "fixed_size",
+            BlockingStrategy::RandomKmeans { .. } => "random_kmeans",
+        }
+    }
+
+    /// The parameters below are `None` if the strategy does not have them.
+    #[getter]
+    pub fn block_size(&self) -> Option<usize> {
+        match self.blocking {
+            BlockingStrategy::FixedSize { block_size } => Some(block_size),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    pub fn centroid_fraction(&self) -> Option<f32> {
+        match self.blocking {
+            BlockingStrategy::RandomKmeans {
+                centroid_fraction, ..
+            } => Some(centroid_fraction),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    pub fn truncated_kmeans_training(&self) -> Option<bool> {
+        match self.blocking {
+            BlockingStrategy::RandomKmeans {
+                truncated_kmeans_training,
+                ..
+            } => Some(truncated_kmeans_training),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    pub fn truncation_size(&self) -> Option<usize> {
+        match self.blocking {
+            BlockingStrategy::RandomKmeans {
+                truncation_size, ..
+            } => Some(truncation_size),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    pub fn min_cluster_size(&self) -> Option<usize> {
+        match self.blocking {
+            BlockingStrategy::RandomKmeans {
+                min_cluster_size, ..
+            } => Some(min_cluster_size),
+            _ => None,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.blocking)
+    }
+}
+
+/// The strategy used to compute the summary of each block when building an index.
+#[pyclass(name = "SummarizationStrategy")]
+#[derive(Clone)]
+pub struct PySummarizationStrategy {
+    summarization: SummarizationStrategy,
+}
+
+#[pymethods]
+impl PySummarizationStrategy {
+    #[staticmethod]
+    pub fn fixed_size(n_components: usize) -> Self {
+        Self {
+            summarization: SummarizationStrategy::FixedSize { n_components },
+        }
+    }
+
+    #[staticmethod]
+    pub fn energy_preserving(summary_energy: f32) -> Self {
+        Self {
+            summarization: SummarizationStrategy::EnergyPerserving { summary_energy },
+        }
+    }
+
+    /// The name of the strategy, as the static method creating it, e.g., `"energy_preserving"`.
+    #[getter]
+    pub fn kind(&self) -> &'static str {
+        match self.summarization {
+            SummarizationStrategy::FixedSize { .. } => "fixed_size",
+            SummarizationStrategy::EnergyPerserving { .. } => "energy_preserving",
+        }
+    }
+
+    /// The parameters below are `None` if the strategy does not have them.
+    #[getter]
+    pub fn n_components(&self) -> Option<usize> {
+        match self.summarization {
+            SummarizationStrategy::FixedSize { n_components } => Some(n_components),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    pub fn summary_energy(&self) -> Option<f32> {
+        match self.summarization {
+            SummarizationStrategy::EnergyPerserving { summary_energy } => Some(summary_energy),
+            _ => None,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.summarization)
+    }
+}
+
+/// The configuration used to build an index. Strategies which are not given take their
+/// default value. If given to a build, it replaces all the other building parameters.
+#[pyclass(name = "Configuration")]
+#[derive(Clone)]
+pub struct PyConfiguration {
+    config: Configuration,
+}
+
+#[pymethods]
+impl PyConfiguration {
+    #[new]
+    #[pyo3(signature = (pruning=None, blocking=None, summarization=None))]
+    pub fn new(
+        pruning: Option<PyPruningStrategy>,
+        blocking: Option<PyBlockingStrategy>,
+        summarization: Option<PySummarizationStrategy>,
+    ) -> Self {
+        let mut config = Configuration::default();
+        if let Some(pruning) = pruning {
+            config = config.pruning_strategy(pruning.pruning);
+        }
+        if let Some(blocking) = blocking {
+            config = config.blocking_strategy(blocking.blocking);
+        }
+        if let Some(summarization) = summarization {
+            config = config.summarization_strategy(summarization.summarization);
+        }
+
+        Self { config }
+    }
+
+    #[getter]
+    pub fn pruning(&self) -> PyPruningStrategy {
+        PyPruningStrategy {
+            pruning: self.config.pruning().clone(),
+        }
+    }
+
+    #[getter]
+    pub fn blocking(&self) -> PyBlockingStrategy {
+        PyBlockingStrategy {
+            blocking: self.config.blocking().clone(),
+        }
+    }
+
+    #[getter]
+    pub fn summarization(&self) -> PySummarizationStrategy {
+        PySummarizationStrategy {
+            summarization: self.config.summarization().clone(),
+        }
+    }
+
+    /// The quantization of the values of the forward index, `"bits8"` or `"bits4"`,
+    /// or `None` if it is not compressed.
+    #[getter]
+    pub fn forward_index_quantization(&self) -> Option<&'static str> {
+        match self.config.forward_index() {
+            ForwardIndexStrategy::Uncompressed => None,
+            ForwardIndexStrategy::Compressed {
+                quantization: ValueQuantization::Bits8,
+            } => Some("bits8"),
+            ForwardIndexStrategy::Compressed {
+                quantization: ValueQuantization::Bits4,
+            } => Some("bits4"),
+        }
+    }
+
+    /// Whether the postings of each block are compressed.
+    #[getter]
+    pub fn compressed_postings(&self) -> bool {
+        *self.config.postings() == PostingsStrategy::Compressed
+    }
+
+    /// The order of the blocks of each posting list, `"clustering"` or `"max_summary_weight"`.
+    #[getter]
+    pub fn block_order(&self) -> &'static str {
+        match self.config.block_ordering() {
+            BlockOrder::Clustering => "clustering",
+            BlockOrder::MaxSummaryWeight => "max_summary_weight",
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.config)
+    }
+}
+
+/// How the passages of a document are aggregated to score it, when the index stores
+/// the document of each passage and results are grouped by document.
+#[pyclass(name = "DocumentAggregation")]
+#[derive(Clone)]
+pub struct PyDocumentAggregation {
+    aggregation: DocumentAggregation,
+}
+
+#[pymethods]
+impl PyDocumentAggregation {
+    #[staticmethod]
+    pub fn max_passage() -> Self {
+        Self {
+            aggregation: DocumentAggregation::MaxPassage,
+        }
+    }
+
+    #[staticmethod]
+    pub fn sum_top_passages(m: usize) -> Self {
+        Self {
+            aggregation: DocumentAggregation::SumTopPassages { m },
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.aggregation)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn search_configuration(
+    query_cut: usize,
+    heap_factor: f32,
+    n_candidates: Option<usize>,
+    query_pruning: Option<PyQueryPruning>,
+    dynamic_termination: bool,
+    best_first: bool,
+    block_scan: &str,
+    document_aggregation: Option<PyDocumentAggregation>,
+) -> PyResult<SearchConfiguration> {
+    let block_scan = match block_scan {
+        "full" => BlockScan::Full,
+        "sorted" => BlockScan::SortedBySummary,
+        "early_break" => BlockScan::EarlyBreak,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "Unknown block scan '{block_scan}', expected 'full', 'sorted' or 'early_break'"
+            )))
+        }
+    };
+
+    if n_candidates.is_some() && document_aggregation.is_some() {
+        return Err(PyValueError::new_err(
+            "Reranking is not supported when results are grouped by document, \
+             n_candidates and document_aggregation cannot be both given",
+        ));
+    }
+
+    let config = SearchConfiguration::default()
+        .query_cut(query_cut)
+        .heap_factor(heap_factor)
+        .traversal(if best_first {
+            BlockTraversal::BestFirst
+        } else {
+            BlockTraversal::ListByList
+        })
+        .block_scan(block_scan)
+        .dynamic_termination(dynamic_termination)
+        .rerank(n_candidates)
+        .document_aggregation(document_aggregation.map(|aggregation| aggregation.aggregation));
+
+    Ok(match query_pruning {
+        Some(query_pruning) => config.query_pruning(query_pruning.query_pruning),
+        None => config,
+    })
+}
+
+fn read_dataset(path: &str) -> PyResult<SparseDataset<f32>> {
+    SparseDataset::<f32>::read_bin_file(path).map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+// Copies a query given as NumPy arrays. Raises a `ValueError` if an array is not contiguous or
+// if a component does not fit into 16 bits.
+fn query_vectors(
+    query_components: PyReadonlyArrayDyn<'_, i32>,
+    query_values: PyReadonlyArrayDyn<'_, f32>,
+) -> PyResult<(Vec<u16>, Vec<f32>)> {
+    let query_components = query_components
+        .to_vec()
+        .map_err(|e| PyValueError::new_err(e.to_string()))?
+        .into_iter()
+        .map(|c| {
+            u16::try_from(c).map_err(|_| {
+                PyValueError::new_err(format!("The query component {c} does not fit into 16 bits"))
             })
-            .summarization_strategy(SummarizationStrategy::EnergyPerserving {
-                summary_energy,
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    let query_values = query_values
+        .to_vec()
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+    Ok((query_components, query_values))
+}
+
+// Returns a read-only NumPy array backed by `slice`, which is owned by `owner`.
+//
+// SAFETY: `slice` must not be modified or reallocated as long as `owner` is alive.
+unsafe fn borrowed_array<'py, T: Element>(
+    slice: &[T],
+    owner: &'py PyAny,
+) -> PyResult<&'py PyArray1<T>> {
+    let array = PyArray1::borrow_from_array(&ArrayView1::from(slice), owner);
+    array.call_method(
+        "setflags",
+        (),
+        Some([("write", false)].into_py_dict(owner.py())),
+    )?;
+
+    Ok(array)
+}
+
+/// A dataset of sparse vectors with `f32` values.
+///
+/// The arrays returned by `offsets`, `components`, `values` and `get` are read-only views
+/// of the data of the dataset, which is never modified.
+/// The dataset can be given to `PySeismicIndex.build` and `PySeismicIndex.batch_search`
+/// in place of a file path.
+#[pyclass(name = "SparseDataset")]
+pub struct PySparseDataset {
+    dataset: SparseDataset<f32>,
+}
+
+#[pymethods]
+impl PySparseDataset {
+    /// Reads a dataset from a binary file in the Seismic inner format.
+    #[staticmethod]
+    pub fn load(path: &str) -> PyResult<Self> {
+        Ok(Self {
+            dataset: read_dataset(path)?,
+        })
+    }
+
+    /// Creates a dataset from CSR arrays, as in `PySeismicIndex.build_from_arrays`.
+    #[staticmethod]
+    pub fn from_arrays(
+        py: Python<'_>,
+        indptr: &PyAny,
+        indices: &PyAny,
+        data: &PyAny,
+    ) -> PyResult<Self> {
+        let mut dataset = SparseDatasetMut::<f32>::new();
+        push_csr_rows(py, &mut dataset, indptr, indices, data, false)?;
+
+        Ok(Self {
+            dataset: dataset.into(),
+        })
+    }
+
+    /// Writes the dataset to a binary file in the Seismic inner format.
+    pub fn save(&self, path: &str) -> PyResult<()> {
+        self.dataset
+            .write_bin_file(path)
+            .map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    fn __len__(&self) -> usize {
+        self.dataset.len()
+    }
+
+    #[getter]
+    pub fn dim(&self) -> usize {
+        self.dataset.dim()
+    }
+
+    #[getter]
+    pub fn nnz(&self) -> usize {
+        self.dataset.nnz()
+    }
+
+    #[getter]
+    pub fn offsets(this: &PyCell<Self>) -> PyResult<&PyArray1<usize>> {
+        // SAFETY: The dataset is never modified.
+        unsafe { borrowed_array(this.borrow().dataset.offsets(), this) }
+    }
+
+    #[getter]
+    pub fn components(this: &PyCell<Self>) -> PyResult<&PyArray1<u16>> {
+        // SAFETY: The dataset is never modified.
+        unsafe { borrowed_array(this.borrow().dataset.components(), this) }
+    }
+
+    #[getter]
+    pub fn values(this: &PyCell<Self>) -> PyResult<&PyArray1<f32>> {
+        // SAFETY: The dataset is never modified.
+        unsafe { borrowed_array(this.borrow().dataset.values(), this) }
+    }
+
+    /// Returns the components and the values of the vector with index `id`.
+    pub fn get(this: &PyCell<Self>, id: usize) -> PyResult<(&PyArray1<u16>, &PyArray1<f32>)> {
+        let this_ref = this.borrow();
+        if id >= this_ref.dataset.len() {
+            return Err(PyIndexError::new_err(format!(
+                "The id {id} is out of range"
+            )));
+        }
+
+        let (components, values) = this_ref.dataset.get(id);
+        // SAFETY: The dataset is never modified.
+        unsafe {
+            Ok((
+                borrowed_array(components, this)?,
+                borrowed_array(values, this)?,
+            ))
+        }
+    }
+
+    /// Exact search of the top-`k` vectors by dot product with the query.
+    pub fn search<'py>(
+        &self,
+        py: Python<'py>,
+        query_components: PyReadonlyArrayDyn<'py, i32>,
+        query_values: PyReadonlyArrayDyn<'py, f32>,
+        k: usize,
+    ) -> PyResult<Vec<(f32, usize)>> {
+        let (query_components, query_values) = query_vectors(query_components, query_values)?;
+
+        Ok(py.allow_threads(|| self.dataset.search(&query_components, &query_values, k)))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "SparseDataset(len={}, dim={}, nnz={})",
+            self.dataset.len(),
+            self.dataset.dim(),
+            self.dataset.nnz()
+        )
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_configuration(
+    n_postings: usize,
+    centroid_fraction: f32,
+    truncated_kmeans_training: bool,
+    truncation_size: usize,
+    min_cluster_size: usize,
+    summary_energy: f32,
+    max_fraction: f32,
+    pruning_strategy: Option<PyPruningStrategy>,
+    config: Option<PyConfiguration>,
+) -> Configuration {
+    if let Some(config) = config {
+        return config.config;
+    }
+
+    let pruning = match pruning_strategy {
+        Some(pruning_strategy) => pruning_strategy.pruning,
+        None => PruningStrategy::GlobalThreshold {
+            n_postings,
+            max_fraction,
+        },
+    };
+
+    Configuration::default()
+        .pruning_strategy(pruning)
+        .blocking_strategy(BlockingStrategy::RandomKmeans {
+            centroid_fraction,
+            truncated_kmeans_training,
+            truncation_size,
+            min_cluster_size,
+        })
+        .summarization_strategy(SummarizationStrategy::EnergyPerserving { summary_energy })
+}
+
+// Converts `array` into a contiguous NumPy array of type `dtype`. No copy is made if it is already so.
+fn contiguous_array<'py, T: Element>(
+    py: Python<'py>,
+    array: &'py PyAny,
+    dtype: &str,
+) -> PyResult<PyReadonlyArray1<'py, T>> {
+    py.import("numpy")?
+        .call_method1("ascontiguousarray", (array, dtype))?
+        .extract()
+}
+
+// A NumPy array of indices, borrowed without copying if it already has 32 or 64-bit integers.
+enum IndexArray<'py> {
+    Int32(PyReadonlyArray1<'py, i32>),
+    Int64(PyReadonlyArray1<'py, i64>),
+}
+
+impl<'py> IndexArray<'py> {
+    // Arrays of other types are converted to 64-bit integers.
+    fn new(py: Python<'py>, array: &'py PyAny) -> PyResult<Self> {
+        let array = py.import("numpy")?.call_method1("asarray", (array,))?;
+        let dtype: String = array.getattr("dtype")?.str()?.extract()?;
+
+        Ok(if dtype == "int32" {
+            Self::Int32(contiguous_array(py, array, "int32")?)
+        } else {
+            Self::Int64(contiguous_array(py, array, "int64")?)
+        })
+    }
+}
+
+// Appends to `dataset` the vectors of a dataset in CSR format. Empty vectors are an error,
+// unless `skip_empty` is true, and then they are not appended. Returns whether each vector
+// has been appended.
+// `indptr` and `indices` may be arrays of 32 or 64-bit integers. As in `scipy.sparse`, the
+// components of a vector may be unsorted and repeated: they are sorted, and the values of a
+// repeated component are summed.
+fn push_csr_rows<T>(
+    py: Python<'_>,
+    dataset: &mut SparseDatasetMut<T>,
+    indptr: &PyAny,
+    indices: &PyAny,
+    data: &PyAny,
+    skip_empty: bool,
+) -> PyResult<Vec<bool>>
+where
+    T: DataType,
+    f32: AsPrimitive<T>,
+{
+    let indptr = IndexArray::new(py, indptr)?;
+    let indices = IndexArray::new(py, indices)?;
+    let data = contiguous_array::<f32>(py, data, "float32")?;
+    let data = data.as_slice()?;
+
+    match (&indptr, &indices) {
+        (IndexArray::Int32(p), IndexArray::Int32(i)) => {
+            push_csr_slices(dataset, p.as_slice()?, i.as_slice()?, data, skip_empty)
+        }
+        (IndexArray::Int32(p), IndexArray::Int64(i)) => {
+            push_csr_slices(dataset, p.as_slice()?, i.as_slice()?, data, skip_empty)
+        }
+        (IndexArray::Int64(p), IndexArray::Int32(i)) => {
+            push_csr_slices(dataset, p.as_slice()?, i.as_slice()?, data, skip_empty)
+        }
+        (IndexArray::Int64(p), IndexArray::Int64(i)) => {
+            push_csr_slices(dataset, p.as_slice()?, i.as_slice()?, data, skip_empty)
+        }
+    }
+}
+
+fn push_csr_slices<T, P, I>(
+    dataset: &mut SparseDatasetMut<T>,
+    indptr: &[P],
+    indices: &[I],
+    data: &[f32],
+    skip_empty: bool,
+) -> PyResult<Vec<bool>>
+where
+    T: DataType,
+    f32: AsPrimitive<T>,
+    P: AsPrimitive<i64>,
+    I: AsPrimitive<i64>,
+{
+    if indices.len() != data.len() {
+        return Err(PyValueError::new_err(
+            "indices and data must have the same length",
+        ));
+    }
+
+    let mut row: Vec<(u16, f32)> = Vec::new();
+    let mut components = Vec::new();
+    let mut sums = Vec::new();
+    let mut values: Vec<T> = Vec::new();
+    let mut is_pushed = Vec::with_capacity(indptr.len().saturating_sub(1));
+    for (i, range) in indptr.windows(2).enumerate() {
+        let (start, end): (i64, i64) = (range[0].as_(), range[1].as_());
+        if start < 0 || start > end || end as usize > indices.len() {
+            return Err(PyValueError::new_err(format!(
+                "indptr is not valid at vector {i}"
+            )));
+        }
+        let (start, end) = (start as usize, end as usize);
+        if start == end {
+            if skip_empty {
+                is_pushed.push(false);
+                continue;
+            }
+            return Err(PyValueError::new_err(format!("The vector {i} is empty")));
+        }
+
+        row.clear();
+        for (&c, &v) in indices[start..end].iter().zip(&data[start..end]) {
+            let c: i64 = c.as_();
+            if !(0..=u16::MAX as i64).contains(&c) {
+                return Err(PyValueError::new_err(format!(
+                    "The component {c} of vector {i} does not fit into 16 bits"
+                )));
+            }
+            row.push((c as u16, v));
+        }
+        row.sort_by_key(|&(c, _)| c);
+
+        components.clear();
+        sums.clear();
+        for &(c, v) in row.iter() {
+            if components.last() == Some(&c) {
+                *sums.last_mut().unwrap() += v;
+            } else {
+                components.push(c);
+                sums.push(v);
+            }
+        }
+        values.clear();
+        values.extend(sums.iter().map(|&v| v.as_()));
+        dataset.push(&components, &values);
+        is_pushed.push(true);
+    }
+
+    Ok(is_pushed)
+}
+
+/// Collects a dataset in batches, e.g., while it is being encoded, and builds an index from it.
+/// Each batch is given in CSR format, as in `PySeismicIndex.build_from_arrays`.
+#[pyclass]
+#[derive(Default)]
+pub struct PySeismicIndexBuilder {
+    dataset: SparseDatasetMut<f16>,
+}
+
+#[pymethods]
+impl PySeismicIndexBuilder {
+    #[new]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a batch of vectors in CSR format.
+    pub fn add_batch(
+        &mut self,
+        py: Python<'_>,
+        indptr: &PyAny,
+        indices: &PyAny,
+        data: &PyAny,
+    ) -> PyResult<()> {
+        push_csr_rows(py, &mut self.dataset, indptr, indices, data, false)?;
+        Ok(())
+    }
+
+    fn __len__(&self) -> usize {
+        self.dataset.len()
+    }
+
+    /// Builds the index with the vectors added so far. The builder is left empty.
+    /// If given, `progress_callback` is called with the progress of the construction,
+    /// see `SeismicIndex.build`.
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (n_postings=6000, centroid_fraction=0.1, truncated_kmeans_training=false, truncation_size=16, min_cluster_size=2, summary_energy=0.5, max_fraction=1.5, pruning_strategy=None, config=None, progress_callback=None))]
+    pub fn build(
+        &mut self,
+        py: Python<'_>,
+        n_postings: usize,
+        centroid_fraction: f32,
+        truncated_kmeans_training: bool,
+        truncation_size: usize,
+        min_cluster_size: usize,
+        summary_energy: f32,
+        max_fraction: f32,
+        pruning_strategy: Option<PyPruningStrategy>,
+        config: Option<PyConfiguration>,
+        progress_callback: Option<PyObject>,
+    ) -> PyResult<PySeismicIndex> {
+        let config = build_configuration(
+            n_postings,
+            centroid_fraction,
+            truncated_kmeans_training,
+            truncation_size,
+            min_cluster_size,
+            summary_energy,
+            max_fraction,
+            pruning_strategy,
+            config,
+        );
+
+        PySeismicIndex::build_with_config(
+            py,
+            std::mem::take(&mut self.dataset).into(),
+            config,
+            progress_callback,
+        )
+    }
+}
+
+// Forwards the notifications on the construction of an index to a Python callable, if any,
+// and checks for signals, e.g., a `KeyboardInterrupt`. Notifications are serialized. The first
+// exception raised by the callable or by a signal handler cancels the construction, and is
+// raised once it is abandoned.
+struct PyBuildObserver {
+    callback: Option<PyObject>,
+    total: AtomicUsize,
+    processed: AtomicUsize,
+    error: Mutex<Option<PyErr>>,
+}
+
+impl PyBuildObserver {
+    fn new(callback: Option<PyObject>) -> Self {
+        Self {
+            callback,
+            total: AtomicUsize::new(0),
+            processed: AtomicUsize::new(0),
+            error: Mutex::new(None),
+        }
+    }
+
+    fn notify(&self, args: impl IntoPy<Py<PyTuple>>) {
+        let mut error = self.error.lock().unwrap();
+        if error.is_none() {
+            Python::with_gil(|py| {
+                *error = py
+                    .check_signals()
+                    .and_then(|_| match &self.callback {
+                        Some(callback) => callback.call1(py, args).map(|_| ()),
+                        None => Ok(()),
+                    })
+                    .err()
             });
-        println!("\nBuilding the index...");
-        println!("{:?}", config);
+        }
+    }
+}
+
+impl BuildObserver for PyBuildObserver {
+    fn phase_started(&self, phase: BuildPhase, total: Option<usize>) {
+        self.total.store(total.unwrap_or(0), Ordering::Relaxed);
+        self.processed.store(0, Ordering::Relaxed);
+        self.notify(("started", phase.name(), total));
+    }
+
+    fn progress(&self, phase: BuildPhase, n: usize) {
+        let step = (self.total.load(Ordering::Relaxed) / 100).max(1);
+        let processed = self.processed.fetch_add(n, Ordering::Relaxed) + n;
+        if processed / step > (processed - n) / step {
+            self.notify(("progress", phase.name(), processed));
+        }
+    }
+
+    fn phase_finished(&self, phase: BuildPhase, elapsed: Duration) {
+        self.notify(("finished", phase.name(), elapsed.as_secs_f64()));
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.error.lock().unwrap().is_some()
+    }
+}
+
+#[pyclass]
+pub struct PySeismicIndex {
+    // Shared with the `IndexHandle` which returned it, if any.
+    inverted_index: Arc<InvertedIndex<f16>>,
+}
+
+impl Default for PySeismicIndex {
+    fn default() -> Self {
+        Self {
+            inverted_index: Arc::new(InvertedIndex::build(
+                SparseDatasetMut::new().into(),
+                Configuration::default(),
+            )),
+        }
+    }
+}
+
+impl PySeismicIndex {
+    // Builds the index without holding the GIL. The progress is reported to `progress_callback`,
+    // if given. The first exception it raises, or a `KeyboardInterrupt`, cancels the construction
+    // and is returned.
+    fn build_with_config(
+        py: Python<'_>,
+        dataset: SparseDataset<f16>,
+        config: Configuration,
+        progress_callback: Option<PyObject>,
+    ) -> PyResult<Self> {
+        let observer = PyBuildObserver::new(progress_callback);
+        let result =
+            py.allow_threads(|| InvertedIndex::build_with_observer(dataset, config, &observer));
+
+        // The construction may have been cancelled, or may have finished before noticing the error.
+        if let Some(error) = observer.error.into_inner().unwrap() {
+            return Err(error);
+        }
+        let inverted_index = result.map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+
+        Ok(Self {
+            inverted_index: Arc::new(inverted_index),
+        })
+    }
+
+    // Returns the index to modify it. An index shared with an `IndexHandle` is not copied,
+    // as its copy would silently diverge from the index served by the handle.
+    fn exclusive_index(&mut self) -> PyResult<&mut InvertedIndex<f16>> {
+        Arc::get_mut(&mut self.inverted_index).ok_or_else(|| {
+            PyRuntimeError::new_err(
+                "The index is shared with an IndexHandle and cannot be modified. \
+                 Modify the index before giving it to the handle, then swap it in.",
+            )
+        })
+    }
+
+    // Searches all the `queries` in parallel with `num_threads` threads, without holding the GIL.
+    fn search_dataset(
+        &self,
+        py: Python<'_>,
+        queries: &SparseDataset<f32>,
+        k: usize,
+        num_threads: usize,
+        search_config: &SearchConfiguration,
+    ) -> PyResult<Vec<Vec<(f32, usize)>>> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+
+        Ok(py.allow_threads(|| {
+            pool.install(|| {
+                queries
+                    .par_iter()
+                    .map(|(components, values)| {
+                        self.inverted_index
+                            .search_with_config(components, values, k, search_config)
+                    })
+                    .collect()
+            })
+        }))
+    }
+}
+
+#[pymethods]
+impl PySeismicIndex {
+    #[staticmethod]
+    pub fn load(index_path: &str) -> PyResult<PySeismicIndex> {
+        let serialized: Vec<u8> =
+            fs::read(index_path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        Self::from_bytes(&serialized)
+    }
+
+    /// Writes the index to the file at `path`.
+    pub fn save(&self, py: Python<'_>, path: &str) -> PyResult<()> {
+        py.allow_threads(|| {
+            let file = File::create(path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+            let mut writer = BufWriter::new(file);
+            bincode::serialize_into(&mut writer, &*self.inverted_index)
+                .map_err(|e| PyIOError::new_err(e.to_string()))?;
+            writer
+                .flush()
+                .map_err(|e| PyIOError::new_err(e.to_string()))
+        })
+    }
+
+    /// Serializes the index into bytes, in the same format written by `save`.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        let serialized = py
+            .allow_threads(|| bincode::serialize(&*self.inverted_index))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyBytes::new(py, &serialized))
+    }
+
+    /// Deserializes an index from the bytes returned by `to_bytes`.
+    #[staticmethod]
+    pub fn from_bytes(serialized: &[u8]) -> PyResult<PySeismicIndex> {
+        let inverted_index = bincode::deserialize::<InvertedIndex<f16>>(serialized)
+            .map_err(|e| PyValueError::new_err(format!("Invalid Seismic index: {e}")))?;
+        Ok(PySeismicIndex {
+            inverted_index: Arc::new(inverted_index),
+        })
+    }
+
+    /// Creates an empty index. It is only useful to unpickle an index with `__setstate__`.
+    #[new]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pickles the index into the bytes returned by `to_bytes`.
+    pub fn __getstate__<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        self.to_bytes(py)
+    }
+
+    /// Unpickles the index from the bytes returned by `__getstate__`.
+    pub fn __setstate__(&mut self, py: Python<'_>, state: &PyBytes) -> PyResult<()> {
+        let serialized = state.as_bytes();
+        let inverted_index = py
+            .allow_threads(|| bincode::deserialize::<InvertedIndex<f16>>(serialized))
+            .map_err(|e| PyValueError::new_err(format!("Invalid Seismic index: {e}")))?;
+        self.inverted_index = Arc::new(inverted_index);
+        Ok(())
+    }
 
-        let inverted_index = InvertedIndex::build(dataset, config);
-        Ok(PySeismicIndex { inverted_index })
+    /// Merges two indexes built with the same configuration into a new index, where the
+    /// documents of `b` follow those of `a`. See `InvertedIndex::merge`.
+    #[staticmethod]
+    pub fn merge(py: Python<'_>, a: &PySeismicIndex, b: &PySeismicIndex) -> PyResult<Self> {
+        let (a, b) = (Arc::clone(&a.inverted_index), Arc::clone(&b.inverted_index));
+        let inverted_index = py
+            .allow_threads(|| {
+                InvertedIndex::merge(Arc::unwrap_or_clone(a), Arc::unwrap_or_clone(b))
+            })
+            .map_err(|e| PyValueError::new_err(format!("Cannot merge the indexes: {e}")))?;
+
+        Ok(Self {
+            inverted_index: Arc::new(inverted_index),
+        })
+    }
+
+    /// Returns a new index with the summaries of the blocks computed with `summarization`.
+    /// The postings and the blocks are the same. See `InvertedIndex::resummarize`.
+    pub fn resummarize(
+        &self,
+        py: Python<'_>,
+        summarization: PySummarizationStrategy,
+    ) -> PySeismicIndex {
+        let inverted_index = (*self.inverted_index).clone();
+        let inverted_index =
+            py.allow_threads(|| inverted_index.resummarize(summarization.summarization));
+
+        PySeismicIndex {
+            inverted_index: Arc::new(inverted_index),
+        }
+    }
+
+    /// Returns a new, smaller index with the posting lists pruned again with `pruning`, which
+    /// must be at least as tight as the current pruning strategy. See `InvertedIndex::reprune`.
+    pub fn reprune(&self, py: Python<'_>, pruning: PyPruningStrategy) -> PyResult<PySeismicIndex> {
+        // Checked before copying the index, which is dropped by a failed repruning.
+        if !pruning
+            .pruning
+            .is_as_tight_as(self.inverted_index.config().pruning())
+        {
+            return Err(PyValueError::new_err(format!(
+                "The pruning strategy {:?} is not at least as tight as the current one, {:?}",
+                pruning.pruning,
+                self.inverted_index.config().pruning()
+            )));
+        }
+
+        let inverted_index = (*self.inverted_index).clone();
+        let inverted_index = py
+            .allow_threads(|| inverted_index.reprune(pruning.pruning))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        Ok(PySeismicIndex {
+            inverted_index: Arc::new(inverted_index),
+        })
+    }
+
+    /// Builds the index from `input_file`, either the path of a file in the Seismic inner
+    /// format or a `SparseDataset`.
+    ///
+    /// If given, `progress_callback` is called as `progress_callback(event, phase, value)`
+    /// while the index is built, where `phase` is the name of a phase of the construction, e.g.,
+    /// `"building_posting_lists"`, and `event` is one of:
+    /// - `"started"`, with `value` the number of items of the phase, or `None` if unknown;
+    /// - `"progress"`, with `value` the number of items processed so far, at most once per percent;
+    /// - `"finished"`, with `value` the duration of the phase in seconds.
+    ///
+    /// An exception raised by `progress_callback`, or a `KeyboardInterrupt`, stops the
+    /// construction at the next phase or posting list, and is raised.
+    #[staticmethod]
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (input_file, n_postings=6000, centroid_fraction=0.1, truncated_kmeans_training=false, truncation_size=16, min_cluster_size=2, summary_energy=0.5, max_fraction=1.5, pruning_strategy=None, config=None, progress_callback=None))]
+    pub fn build(
+        py: Python<'_>,
+        input_file: &PyAny,
+        n_postings: usize,
+        centroid_fraction: f32,
+        truncated_kmeans_training: bool,
+        truncation_size: usize,
+        min_cluster_size: usize,
+        summary_energy: f32,
+        max_fraction: f32,
+        pruning_strategy: Option<PyPruningStrategy>,
+        config: Option<PyConfiguration>,
+        progress_callback: Option<PyObject>,
+    ) -> PyResult<PySeismicIndex> {
+        let dataset = match input_file.extract::<PyRef<PySparseDataset>>() {
+            Ok(dataset) => dataset.dataset.clone(),
+            Err(_) => read_dataset(input_file.extract()?)?,
+        }
+        .quantize_f16();
+
+        let config = build_configuration(
+            n_postings,
+            centroid_fraction,
+            truncated_kmeans_training,
+            truncation_size,
+            min_cluster_size,
+            summary_energy,
+            max_fraction,
+            pruning_strategy,
+            config,
+        );
+
+        PySeismicIndex::build_with_config(py, dataset, config, progress_callback)
+    }
+
+    /// Builds the index from a dataset in CSR format, e.g., the `indptr`, `indices` and `data`
+    /// arrays of a `scipy.sparse.csr_matrix`. The vector `i` has components
+    /// `indices[indptr[i]:indptr[i+1]]` with values `data[indptr[i]:indptr[i+1]]`.
+    /// `indptr` and `indices` can be 32 or 64-bit integers, and are not copied if contiguous.
+    /// The components of a vector need not be sorted, and the values of a repeated component are summed.
+    /// See `build` for `progress_callback`.
+    #[staticmethod]
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (indptr, indices, data, n_postings=6000, centroid_fraction=0.1, truncated_kmeans_training=false, truncation_size=16, min_cluster_size=2, summary_energy=0.5, max_fraction=1.5, pruning_strategy=None, config=None, progress_callback=None))]
+    pub fn build_from_arrays(
+        py: Python<'_>,
+        indptr: &PyAny,
+        indices: &PyAny,
+        data: &PyAny,
+        n_postings: usize,
+        centroid_fraction: f32,
+        truncated_kmeans_training: bool,
+        truncation_size: usize,
+        min_cluster_size: usize,
+        summary_energy: f32,
+        max_fraction: f32,
+        pruning_strategy: Option<PyPruningStrategy>,
+        config: Option<PyConfiguration>,
+        progress_callback: Option<PyObject>,
+    ) -> PyResult<PySeismicIndex> {
+        let mut dataset = SparseDatasetMut::<f16>::new();
+        push_csr_rows(py, &mut dataset, indptr, indices, data, false)?;
+
+        let config = build_configuration(
+            n_postings,
+            centroid_fraction,
+            truncated_kmeans_training,
+            truncation_size,
+            min_cluster_size,
+            summary_energy,
+            max_fraction,
+            pruning_strategy,
+            config,
+        );
+
+        PySeismicIndex::build_with_config(py, dataset.into(), config, progress_callback)
     }
 
+    /// The configuration used to build the index.
+    #[getter]
+    pub fn config(&self) -> PyConfiguration {
+        PyConfiguration {
+            config: self.inverted_index.config().clone(),
+        }
+    }
+
+    /// Returns statistics on the posting lists, the blocks, the summaries, and the space usage
+    /// of the index, as a dictionary with the same fields printed by `inspect_index`.
+    pub fn stats<'py>(&self, py: Python<'py>) -> PyResult<&'py PyAny> {
+        let stats = serde_json::to_string(&self.inverted_index.stats())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        py.import("json")?.call_method1("loads", (stats,))
+    }
+
+    /// Checks the invariants of the index and returns the description of each violation found.
+    /// The list is empty if the index is valid.
+    pub fn validate(&self, py: Python<'_>) -> Vec<String> {
+        py.allow_threads(|| match self.inverted_index.validate() {
+            Ok(()) => Vec::new(),
+            Err(violations) => violations.iter().map(|v| v.to_string()).collect(),
+        })
+    }
+
+    /// Stores in the index the original-precision vectors read from `input_file`,
+    /// which are used to rerank candidates when `n_candidates` is given to search.
+    /// Raises an error if the index is shared with an `IndexHandle`.
+    pub fn load_rerank_file(&mut self, input_file: &str) -> PyResult<()> {
+        let inverted_index = self.exclusive_index()?;
+        let rerank_dataset = SparseDataset::<f32>::read_bin_file(input_file)
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        *inverted_index = std::mem::take(inverted_index).with_rerank_dataset(rerank_dataset);
+        Ok(())
+    }
+
+    /// Stores the document of each passage in the index. Searches with a `document_aggregation`
+    /// return the top-k distinct documents instead of passages.
+    /// Raises an error if the index is shared with an `IndexHandle`.
+    pub fn set_passage_documents(&mut self, passage_documents: Vec<usize>) -> PyResult<()> {
+        let inverted_index = self.exclusive_index()?;
+        *inverted_index = std::mem::take(inverted_index).with_passage_documents(passage_documents);
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (query_components, query_values, k, query_cut, heap_factor, n_candidates=None, query_pruning=None, dynamic_termination=false, best_first=false, block_scan="full", document_aggregation=None))]
     pub fn search<'py>(
         &self,
+        py: Python<'py>,
         query_components: PyReadonlyArrayDyn<'py, i32>,
         query_values: PyReadonlyArrayDyn<'py, f32>,
         k: usize,
         query_cut: usize,
         heap_factor: f32,
-    ) -> Vec<(f32, usize)> {
-        self.inverted_index.search(
-            &query_components
-                .to_vec()
-                .unwrap()
-                .iter()
-                .map(|x| *x as u16)
-                .collect::<Vec<_>>(),
-            &query_values.to_vec().unwrap(),
-            k,
+        n_candidates: Option<usize>,
+        query_pruning: Option<PyQueryPruning>,
+        dynamic_termination: bool,
+        best_first: bool,
+        block_scan: &str,
+        document_aggregation: Option<PyDocumentAggregation>,
+    ) -> PyResult<Vec<(f32, usize)>> {
+        let search_config = search_configuration(
             query_cut,
             heap_factor,
-        )
+            n_candidates,
+            query_pruning,
+            dynamic_termination,
+            best_first,
+            block_scan,
+            document_aggregation,
+        )?;
+
+        let (query_components, query_values) = query_vectors(query_components, query_values)?;
+
+        Ok(py.allow_threads(|| {
+            self.inverted_index.search_with_config(
+                &query_components,
+                &query_values,
+                k,
+                &search_config,
+            )
+        }))
+    }
+
+    /// Returns all the documents whose dot product with the query is at least `threshold`,
+    /// sorted by decreasing dot product. If `max_results` is given, only the best ones are returned.
+    #[pyo3(signature = (query_components, query_values, threshold, max_results=None))]
+    pub fn search_range<'py>(
+        &self,
+        py: Python<'py>,
+        query_components: PyReadonlyArrayDyn<'py, i32>,
+        query_values: PyReadonlyArrayDyn<'py, f32>,
+        threshold: f32,
+        max_results: Option<usize>,
+    ) -> PyResult<Vec<(f32, usize)>> {
+        let (query_components, query_values) = query_vectors(query_components, query_values)?;
+
+        Ok(py.allow_threads(|| {
+            self.inverted_index.search_range(
+                &query_components,
+                &query_values,
+                threshold,
+                max_results,
+            )
+        }))
     }
 
     #[allow(clippy::too_many_arguments)]
-    pub fn batch_search<'py>(
+    #[pyo3(signature = (query_path, k, query_cut, heap_factor, num_threads, n_candidates=None, query_pruning=None, dynamic_termination=false, best_first=false, block_scan="full", document_aggregation=None))]
+    pub fn batch_search(
         &self,
-        query_path: &str,
+        py: Python<'_>,
+        query_path: &PyAny,
         k: usize,
         query_cut: usize,
         heap_factor: f32,
         num_threads: usize,
-    ) -> Vec<Vec<(f32, usize)>> {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(num_threads)
-            .build()
-            .unwrap();
-
-        let queries = SparseDataset::<f32>::read_bin_file(query_path).unwrap();
-
-        queries
-            .par_iter()
-            .map(|query| {
-                self.inverted_index.search(
-                    query.0,
-                    query.1,
-                    k,
-                    query_cut,
-                    heap_factor,
-                )
-            })
-            .collect::<Vec<_>>()
+        n_candidates: Option<usize>,
+        query_pruning: Option<PyQueryPruning>,
+        dynamic_termination: bool,
+        best_first: bool,
+        block_scan: &str,
+        document_aggregation: Option<PyDocumentAggregation>,
+    ) -> PyResult<Vec<Vec<(f32, usize)>>> {
+        let search_config = search_configuration(
+            query_cut,
+            heap_factor,
+            n_candidates,
+            query_pruning,
+            dynamic_termination,
+            best_first,
+            block_scan,
+            document_aggregation,
+        )?;
+
+        if let Ok(queries) = query_path.extract::<PyRef<PySparseDataset>>() {
+            return self.search_dataset(py, &queries.dataset, k, num_threads, &search_config);
+        }
+
+        let queries = read_dataset(query_path.extract()?)?;
+        self.search_dataset(py, &queries, k, num_threads, &search_config)
+    }
+
+    /// Searches a batch of queries in CSR format, as in `build_from_arrays`, with `num_threads` threads.
+    /// Returns a pair `(scores, ids)` of NumPy arrays of shape `(n_queries, k)`, with the results
+    /// of each query by decreasing score. If a query has less than `k` results, its row is padded
+    /// with score `-inf` and id `-1`. An empty query, i.e., a row without components, has no results.
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (indptr, indices, data, k, query_cut, heap_factor, num_threads, n_candidates=None, query_pruning=None, dynamic_termination=false, best_first=false, block_scan="full", document_aggregation=None))]
+    pub fn batch_search_arrays<'py>(
+        &self,
+        py: Python<'py>,
+        indptr: &PyAny,
+        indices: &PyAny,
+        data: &PyAny,
+        k: usize,
+        query_cut: usize,
+        heap_factor: f32,
+        num_threads: usize,
+        n_candidates: Option<usize>,
+        query_pruning: Option<PyQueryPruning>,
+        dynamic_termination: bool,
+        best_first: bool,
+        block_scan: &str,
+        document_aggregation: Option<PyDocumentAggregation>,
+    ) -> PyResult<(&'py PyArray2<f32>, &'py PyArray2<i64>)> {
+        let mut queries = SparseDatasetMut::<f32>::new();
+        // Empty queries have no results, they are not searched.
+        let is_pushed = push_csr_rows(py, &mut queries, indptr, indices, data, true)?;
+        let queries: SparseDataset<f32> = queries.into();
+
+        let search_config = search_configuration(
+            query_cut,
+            heap_factor,
+            n_candidates,
+            query_pruning,
+            dynamic_termination,
+            best_first,
+            block_scan,
+            document_aggregation,
+        )?;
+
+        let results = self.search_dataset(py, &queries, k, num_threads, &search_config)?;
+
+        let mut scores = Array2::from_elem((is_pushed.len(), k), f32::NEG_INFINITY);
+        let mut ids = Array2::from_elem((is_pushed.len(), k), -1_i64);
+        let rows = is_pushed
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &pushed)| pushed.then_some(i));
+        for (i, query_results) in rows.zip(&results) {
+            for (j, &(score, id)) in query_results.iter().take(k).enumerate() {
+                scores[[i, j]] = score;
+                ids[[i, j]] = id as i64;
+            }
+        }
+
+        Ok((scores.into_pyarray(py), ids.into_pyarray(py)))
+    }
+}
+
+/// A handle to an index which can be replaced while it is being searched, e.g., by a service
+/// picking up a new build. `current` returns the index to search: an index returned before a
+/// reload keeps searching the old index, which is freed once it is no longer referenced.
+#[pyclass(name = "IndexHandle")]
+pub struct PyIndexHandle {
+    handle: Arc<IndexHandle<f16>>,
+}
+
+#[pymethods]
+impl PyIndexHandle {
+    #[new]
+    pub fn new(index: &PySeismicIndex) -> Self {
+        Self {
+            handle: Arc::new(IndexHandle::from_arc(Arc::clone(&index.inverted_index))),
+        }
+    }
+
+    #[staticmethod]
+    pub fn load(py: Python<'_>, index_path: &str) -> PyResult<Self> {
+        let handle = py
+            .allow_threads(|| IndexHandle::load(index_path))
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        Ok(Self {
+            handle: Arc::new(handle),
+        })
+    }
+
+    /// Returns the current index.
+    pub fn current(&self) -> PySeismicIndex {
+        PySeismicIndex {
+            inverted_index: self.handle.current(),
+        }
+    }
+
+    /// Replaces the current index with `index`.
+    pub fn swap(&self, index: &PySeismicIndex) {
+        self.handle.swap(Arc::clone(&index.inverted_index));
+    }
+
+    /// Loads the index in the file at `index_path` and replaces the current one with it.
+    /// The current index is kept if the file does not contain a valid index.
+    pub fn reload(&self, py: Python<'_>, index_path: &str) -> PyResult<()> {
+        py.allow_threads(|| self.handle.reload(index_path))
+            .map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// Same as `reload`, but loads the index in a background thread.
+    pub fn reload_in_background(&self, index_path: &str) -> PyReloadTask {
+        PyReloadTask {
+            thread: Some(self.handle.reload_in_background(index_path)),
+        }
+    }
+
+    /// The number of times the index has been replaced.
+    #[getter]
+    pub fn version(&self) -> usize {
+        self.handle.version()
+    }
+}
+
+/// A reload running in the background, returned by `IndexHandle.reload_in_background`.
+#[pyclass(name = "ReloadTask")]
+pub struct PyReloadTask {
+    thread: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+#[pymethods]
+impl PyReloadTask {
+    /// Returns whether the reload is over.
+    pub fn done(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_none_or(|thread| thread.is_finished())
+    }
+
+    /// Waits for the reload to be over and raises an exception if it failed.
+    pub fn wait(&mut self, py: Python<'_>) -> PyResult<()> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+
+        py.allow_threads(|| thread.join())
+            .map_err(|_| PyRuntimeError::new_err("The reload panicked"))?
+            .map_err(|e| PyIOError::new_err(e.to_string()))
     }
 }
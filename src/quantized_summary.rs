@@ -8,6 +8,9 @@ use qwt::SpaceUsage as QwtSpaceUsage;
 
 use qwt::{DArray, SelectBin};
 
+// The number of levels used to quantize the values of a summary.
+const N_CLASSES: usize = 256;
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 pub struct QuantizedSummary {
     n_summaries: usize,
@@ -30,6 +33,58 @@ impl QuantizedSummary {
             + SpaceUsage::space_usage_byte(&self.quants)
     }
 
+    /// Returns the number of summaries.
+    pub fn n_summaries(&self) -> usize {
+        self.n_summaries
+    }
+
+    /// Returns the number of components of each summary.
+    pub fn summary_lengths(&self) -> Vec<usize> {
+        let mut lengths = vec![0; self.n_summaries];
+        for &s_id in self.summaries_ids.iter() {
+            lengths[s_id as usize] += 1;
+        }
+        lengths
+    }
+
+    /// Returns the range `(minimum, maximum)` of the quantized values of each summary.
+    pub fn value_ranges(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        self.minimums
+            .iter()
+            .zip(self.quants.iter())
+            .map(|(&minimum, &quant)| (minimum, minimum + quant * N_CLASSES as f32))
+    }
+
+    // Checks that the summaries are consistent with each other and have an entry for each of
+    // the `dim` components, so that `matmul_with_query` does not panic.
+    pub(crate) fn is_consistent(&self, dim: usize) -> bool {
+        self.minimums.len() == self.n_summaries
+            && self.quants.len() == self.n_summaries
+            && self.values.len() == self.summaries_ids.len()
+            && self
+                .summaries_ids
+                .iter()
+                .all(|&s_id| (s_id as usize) < self.n_summaries)
+            && self.offsets.count_ones() == dim + 1
+            && self.offsets.select1(dim) == Some(self.summaries_ids.len() + dim)
+    }
+
+    // Extends the summaries with empty entries for the components up to `dim`, so that they can
+    // be queried with the components of an index of larger dimensionality.
+    pub(crate) fn extend_dim(&mut self, dim: usize) {
+        let current_dim = self.offsets.count_ones() - 1;
+        if dim <= current_dim {
+            return;
+        }
+
+        let n_entries = self.summaries_ids.len();
+        self.offsets = self
+            .offsets
+            .ones()
+            .chain((current_dim + 1..=dim).map(|id| n_entries + id))
+            .collect();
+    }
+
     pub fn matmul_with_query(&self, query_components: &[u16], query_values: &[f32]) -> Vec<f32> {
         let mut accumulator = vec![0_f32; self.n_summaries];
 
@@ -66,7 +121,7 @@ impl QuantizedSummary {
         for _ in 0..original_dim {
             inverted_pairs.push(Vec::new());
         }
-        let n_classes = 256;
+        let n_classes = N_CLASSES;
 
         let mut minimums = Vec::with_capacity(inverted_pairs.len());
         let mut quants = Vec::with_capacity(inverted_pairs.len());
@@ -103,7 +158,7 @@ impl QuantizedSummary {
                 .enumerate()
                 .map(|(id, cur_offset)| cur_offset + id) // Add id to make a strictly increasing sequence
                 .collect(),
-                summaries_ids: summaries_ids.into_boxed_slice(),
+            summaries_ids: summaries_ids.into_boxed_slice(),
             values: codes.into_boxed_slice(),
             minimums: minimums.into_boxed_slice(),
             quants: quants.into_boxed_slice(),
@@ -14,7 +14,7 @@ use serde::{Deserialize, Serialize};
 
 // Reading files
 use std::fs::File;
-use std::io::{BufReader, Read, Result as IoResult};
+use std::io::{BufReader, BufWriter, Read, Result as IoResult, Write};
 use std::iter::Zip;
 use std::ops::Range;
 use std::path::Path;
@@ -27,6 +27,7 @@ use half::f16;
 use crate::distances::dot_product_dense_sparse;
 use crate::topk_selectors::{HeapFaiss, OnlineTopKSelector};
 use crate::utils::prefetch_read_NTA;
+use crate::validation::{check_components, Violation};
 use crate::{DataType, SpaceUsage};
 
 // Implementation of a (immutable) sparse dataset.
@@ -347,7 +348,7 @@ where
             .map(|id| {
                 let v_components = &self.components[Self::vector_range(&self.offsets, id)];
                 let v_values = &self.values[Self::vector_range(&self.offsets, id)];
-                -1.0 * dot_product_dense_sparse(&query, v_components, v_values)
+                -dot_product_dense_sparse(&query, v_components, v_values)
             })
             .collect();
 
@@ -379,7 +380,7 @@ where
     ///     assert_eq!(v0, v1);
     /// }
     /// ```
-    pub fn iter(&self) -> SparseDatasetIter<T> {
+    pub fn iter(&self) -> SparseDatasetIter<'_, T> {
         SparseDatasetIter::new(self)
     }
 
@@ -487,6 +488,42 @@ where
         self.components.len()
     }
 
+    /// Returns the offsets of the vectors. The vector with index `i` spans the positions
+    /// from `offsets[i]` to `offsets[i+1]` of the components and the values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use seismic::SparseDataset;
+    ///
+    /// let data = vec![
+    ///                 (vec![0, 2, 4], vec![1.0, 2.0, 3.0]),
+    ///                 (vec![1, 3], vec![4.0, 5.0]),
+    ///                ];
+    ///
+    /// let dataset: SparseDataset<f32> = data.into_iter().collect();
+    ///
+    /// assert_eq!(dataset.offsets(), &[0, 3, 5]);
+    /// assert_eq!(dataset.components(), &[0, 2, 4, 1, 3]);
+    /// assert_eq!(dataset.values(), &[1.0, 2.0, 3.0, 4.0, 5.0]);
+    /// ```
+    #[must_use]
+    pub fn offsets(&self) -> &[usize] {
+        &self.offsets
+    }
+
+    /// Returns the components of all the vectors, one vector after the other.
+    #[must_use]
+    pub fn components(&self) -> &[u16] {
+        &self.components
+    }
+
+    /// Returns the values of all the vectors, one vector after the other.
+    #[must_use]
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
     /// Converts the `offset` of a vector within the dataset to its id, i.e., the position
     /// of the vector within the dataset.
     ///
@@ -570,6 +607,86 @@ where
 
         Ok(data.into())
     }
+
+    /// Writes the dataset to a binary file in the format read by [`SparseDataset::read_bin_file`].
+    /// Values are written as `f32`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use seismic::SparseDataset;
+    ///
+    /// let data = vec![
+    ///                 (vec![0, 2, 4], vec![1.0, 2.0, 3.0]),
+    ///                 (vec![1, 3], vec![4.0, 5.0]),
+    ///                ];
+    ///
+    /// let dataset: SparseDataset<f32> = data.into_iter().collect();
+    ///
+    /// let path = std::env::temp_dir().join("seismic_write_bin_file.bin");
+    /// dataset.write_bin_file(path.to_str().unwrap()).unwrap();
+    ///
+    /// let read = SparseDataset::<f32>::read_bin_file(path.to_str().unwrap()).unwrap();
+    /// assert_eq!(read, dataset);
+    /// # std::fs::remove_file(path).unwrap();
+    /// ```
+    pub fn write_bin_file(&self, fname: &str) -> IoResult<()> {
+        let mut bw = BufWriter::new(File::create(Path::new(fname))?);
+
+        bw.write_all(&(self.len() as u32).to_le_bytes())?;
+        for (components, values) in self.iter() {
+            bw.write_all(&(components.len() as u32).to_le_bytes())?;
+            for &c in components {
+                bw.write_all(&(c as u32).to_le_bytes())?;
+            }
+            for v in values {
+                bw.write_all(&v.to_f32().unwrap().to_le_bytes())?;
+            }
+        }
+
+        bw.flush()
+    }
+
+    // Appends to `violations` the vectors with invalid offsets, unsorted components,
+    // or components not smaller than `dim`.
+    pub(crate) fn check_vectors(&self, dim: usize, violations: &mut Vec<Violation>) {
+        if self.offsets.len() != self.n_vecs + 1 {
+            violations.push(Violation::OffsetsLength {
+                n_vectors: self.n_vecs,
+                n_offsets: self.offsets.len(),
+            });
+            return;
+        }
+        if self.values.len() != self.components.len() {
+            violations.push(Violation::ValuesLength {
+                n_components: self.components.len(),
+                n_values: self.values.len(),
+            });
+            return;
+        }
+
+        for (id, range) in self.offsets.windows(2).enumerate() {
+            let (start, end) = (range[0], range[1]);
+            if start > end || end > self.components.len() {
+                violations.push(Violation::VectorRange {
+                    id,
+                    start,
+                    end,
+                    size: self.components.len(),
+                });
+                continue;
+            }
+
+            check_components(id, &self.components[start..end], dim, violations);
+        }
+    }
+
+    // Same as `offset_to_id`, but returns `None` if `offset` is not the beginning of a vector.
+    #[inline]
+    pub(crate) fn checked_offset_to_id(&self, offset: usize) -> Option<usize> {
+        let offsets = self.offsets.get(..self.n_vecs)?;
+        offsets.binary_search(&offset).ok()
+    }
 }
 
 /// A mutable representation of a sparse dataset.
@@ -875,7 +992,7 @@ where
     ///     assert_eq!(v0, v1);
     /// }
     /// ```
-    pub fn iter(&self) -> SparseDatasetIter<T> {
+    pub fn iter(&self) -> SparseDatasetIter<'_, T> {
         SparseDatasetIter::new_with_mut(self)
     }
 
@@ -895,7 +1012,6 @@ where
     /// # Panics
     ///
     /// Panics if the specified `vec_id` is out of bounds.
-
     pub fn iter_vector(
         &self,
         vec_id: usize,
@@ -1550,4 +1666,19 @@ mod tests {
 
         assert_eq!(vec, result);
     }
+
+    // Repeated components are accepted, and reported by the validator.
+    #[test]
+    fn test_repeated_components() {
+        let data = vec![(vec![0, 2, 2], vec![1.0, 2.0, 3.0])];
+        let dataset: SparseDataset<f32> = data.into_iter().collect();
+        assert_eq!(dataset.nnz(), 3);
+
+        let mut violations = Vec::new();
+        dataset.check_vectors(dataset.dim(), &mut violations);
+        assert_eq!(
+            violations,
+            vec![Violation::UnsortedComponents { id: 0, position: 1 }]
+        );
+    }
 }
@@ -143,7 +143,8 @@ impl OnlineTopKSelector for HeapFaiss {
     ///
     /// # Parameters
     /// - `k` (`usize`): The number of top distances to keep track of.
-    /// # Examples
+    ///
+    /// # Examples
     /// ```
     /// use seismic::topk_selectors::{OnlineTopKSelector, HeapFaiss};
     ///
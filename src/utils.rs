@@ -46,6 +46,47 @@ pub fn prefetch_read_NTA<T>(data: &[T], offset: usize) {
     }
 }
 
+/// Appends `value` to `data` encoded as a variable-length integer.
+/// Every byte stores 7 bits of the value, the most significant bit is set if more bytes follow.
+#[inline]
+pub fn encode_varint(mut value: u64, data: &mut Vec<u8>) {
+    while value >= 0x80 {
+        data.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+/// Decodes a variable-length integer written by [`encode_varint`] at the beginning of `data`.
+/// Returns the value and the remaining bytes.
+///
+/// # Panics
+/// Panics if `data` does not start with a complete variable-length integer.
+#[inline]
+#[must_use]
+pub fn decode_varint(data: &[u8]) -> (u64, &[u8]) {
+    try_decode_varint(data).expect("Truncated variable-length integer")
+}
+
+/// Same as [`decode_varint`], but returns `None` if `data` ends before the end of the integer.
+#[inline]
+pub fn try_decode_varint(data: &[u8]) -> Option<(u64, &[u8])> {
+    let mut value = 0;
+    let mut shift = 0;
+    for (i, &byte) in data.iter().enumerate() {
+        if shift >= u64::BITS {
+            return None;
+        }
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte < 0x80 {
+            return Some((value, &data[i + 1..]));
+        }
+        shift += 7;
+    }
+
+    None
+}
+
 /// Returns the type name of its argument.
 pub fn type_of<T>(_: &T) -> &'static str {
     std::any::type_name::<T>()
@@ -164,4 +205,21 @@ mod tests {
 
         assert_eq!(binary_search_branchless(&data, 198), data.len() - 1);
     }
+
+    #[test]
+    fn test_varint() {
+        let values = [0, 1, 127, 128, 300, 16383, 16384, u32::MAX as u64, u64::MAX];
+        let mut data = Vec::new();
+        for &v in values.iter() {
+            encode_varint(v, &mut data);
+        }
+
+        let mut rest = data.as_slice();
+        for &v in values.iter() {
+            let (decoded, r) = decode_varint(rest);
+            assert_eq!(decoded, v);
+            rest = r;
+        }
+        assert!(rest.is_empty());
+    }
 }
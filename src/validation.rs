@@ -0,0 +1,248 @@
+//! This module defines the violations of the invariants of an index, which are
+//! reported by [`InvertedIndex::validate`](crate::InvertedIndex::validate).
+//!
+//! A violation means that the index is corrupted, e.g., by a truncated or damaged copy of its
+//! file, and that searching it may return wrong results or panic.
+
+use std::fmt;
+
+/// A violated invariant of an [`InvertedIndex`](crate::InvertedIndex).
+///
+/// Lists are identified by their component, blocks by their position in the list,
+/// and postings by their position in the list.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Violation {
+    /// The number of offsets of a dataset is not the number of its vectors plus one.
+    OffsetsLength { n_vectors: usize, n_offsets: usize },
+    /// The offsets of the vector `id` do not delimit a range of the `size` components
+    /// (or bytes, if compressed) of the dataset.
+    VectorRange {
+        id: usize,
+        start: usize,
+        end: usize,
+        size: usize,
+    },
+    /// A dataset has a different number of components and values.
+    ValuesLength {
+        n_components: usize,
+        n_values: usize,
+    },
+    /// The record of the vector `id` in a compressed dataset has a size different from
+    /// the one implied by its header.
+    RecordSize {
+        id: usize,
+        size: usize,
+        expected: usize,
+    },
+    /// The components of the vector `id` are not strictly increasing at `position`.
+    UnsortedComponents { id: usize, position: usize },
+    /// The vector `id` has a component not smaller than the dimensionality of the index.
+    ComponentOutOfRange {
+        id: usize,
+        component: usize,
+        dim: usize,
+    },
+    /// The index does not have a posting list for each component.
+    PostingListCount { n_posting_lists: usize, dim: usize },
+    /// The first block of the list does not start at its first posting.
+    BlockOffsetsStart { list: usize, first: usize },
+    /// The block of the list is empty or ends before it starts.
+    BlockOffsetsNotIncreasing {
+        list: usize,
+        block: usize,
+        start: usize,
+        end: usize,
+    },
+    /// The last block of the list does not end at its last posting.
+    BlockOffsetsEnd {
+        list: usize,
+        last: usize,
+        n_postings: usize,
+    },
+    /// The posting refers to an offset which is not the beginning of a vector in the forward index.
+    PostingOffset {
+        list: usize,
+        position: usize,
+        offset: usize,
+    },
+    /// The length stored in the posting is not the length of its vector in the forward index.
+    PostingLength {
+        list: usize,
+        position: usize,
+        doc_id: usize,
+        len: usize,
+        vector_len: usize,
+    },
+    /// The posting refers to a document which is not in the forward index.
+    PostingDocId {
+        list: usize,
+        position: usize,
+        doc_id: usize,
+        n_documents: usize,
+    },
+    /// The compressed postings of the block cannot be decoded into the number of postings of the block.
+    CompressedBlock { list: usize, block: usize },
+    /// The list does not have a summary for each block.
+    SummaryCount {
+        list: usize,
+        n_summaries: usize,
+        n_blocks: usize,
+    },
+    /// The quantized summaries of the list are inconsistent with each other or with the dimensionality of the index.
+    CorruptedSummaries { list: usize },
+    /// The rerank dataset does not have a vector for each document.
+    RerankDatasetLength { len: usize, n_documents: usize },
+    /// The document of each passage is not stored for every passage.
+    PassageDocumentsLength { len: usize, n_documents: usize },
+    /// A violation in the rerank dataset.
+    RerankDataset(Box<Violation>),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OffsetsLength {
+                n_vectors,
+                n_offsets,
+            } => write!(
+                f,
+                "forward index: {n_offsets} offsets for {n_vectors} vectors, expected {}",
+                n_vectors + 1
+            ),
+            Self::VectorRange {
+                id,
+                start,
+                end,
+                size,
+            } => write!(
+                f,
+                "forward index: vector {id} spans [{start}, {end}), outside of [0, {size})"
+            ),
+            Self::ValuesLength {
+                n_components,
+                n_values,
+            } => write!(
+                f,
+                "forward index: {n_components} components but {n_values} values"
+            ),
+            Self::RecordSize { id, size, expected } => write!(
+                f,
+                "forward index: the record of vector {id} has {size} bytes, expected {expected}"
+            ),
+            Self::UnsortedComponents { id, position } => write!(
+                f,
+                "forward index: the components of vector {id} are not strictly increasing at position {position}"
+            ),
+            Self::ComponentOutOfRange { id, component, dim } => write!(
+                f,
+                "forward index: vector {id} has component {component}, not smaller than the dimensionality {dim}"
+            ),
+            Self::PostingListCount {
+                n_posting_lists,
+                dim,
+            } => write!(
+                f,
+                "{n_posting_lists} posting lists for dimensionality {dim}"
+            ),
+            Self::BlockOffsetsStart { list, first } => write!(
+                f,
+                "posting list {list}: the first block starts at {first} instead of 0"
+            ),
+            Self::BlockOffsetsNotIncreasing {
+                list,
+                block,
+                start,
+                end,
+            } => write!(
+                f,
+                "posting list {list}: block {block} spans [{start}, {end}), which is empty"
+            ),
+            Self::BlockOffsetsEnd {
+                list,
+                last,
+                n_postings,
+            } => write!(
+                f,
+                "posting list {list}: the last block ends at {last} but the list has {n_postings} postings"
+            ),
+            Self::PostingOffset {
+                list,
+                position,
+                offset,
+            } => write!(
+                f,
+                "posting list {list}: posting {position} has offset {offset}, which is not the beginning of a vector"
+            ),
+            Self::PostingLength {
+                list,
+                position,
+                doc_id,
+                len,
+                vector_len,
+            } => write!(
+                f,
+                "posting list {list}: posting {position} has length {len} but vector {doc_id} has {vector_len} components"
+            ),
+            Self::PostingDocId {
+                list,
+                position,
+                doc_id,
+                n_documents,
+            } => write!(
+                f,
+                "posting list {list}: posting {position} refers to document {doc_id} of {n_documents}"
+            ),
+            Self::CompressedBlock { list, block } => write!(
+                f,
+                "posting list {list}: the compressed postings of block {block} cannot be decoded"
+            ),
+            Self::SummaryCount {
+                list,
+                n_summaries,
+                n_blocks,
+            } => write!(
+                f,
+                "posting list {list}: {n_summaries} summaries for {n_blocks} blocks"
+            ),
+            Self::CorruptedSummaries { list } => {
+                write!(f, "posting list {list}: the summaries are corrupted")
+            }
+            Self::RerankDatasetLength { len, n_documents } => write!(
+                f,
+                "the rerank dataset has {len} vectors for {n_documents} documents"
+            ),
+            Self::PassageDocumentsLength { len, n_documents } => write!(
+                f,
+                "{len} passage documents for {n_documents} passages"
+            ),
+            Self::RerankDataset(violation) => write!(
+                f,
+                "rerank dataset: {}",
+                violation.to_string().trim_start_matches("forward index: ")
+            ),
+        }
+    }
+}
+
+// Appends to `violations` the unsorted components of the vector `id`, and the first one
+// not smaller than `dim`.
+pub(crate) fn check_components(
+    id: usize,
+    components: &[u16],
+    dim: usize,
+    violations: &mut Vec<Violation>,
+) {
+    for (position, w) in components.windows(2).enumerate() {
+        if w[0] >= w[1] {
+            violations.push(Violation::UnsortedComponents { id, position });
+        }
+    }
+
+    if let Some(&component) = components.iter().find(|&&c| c as usize >= dim) {
+        violations.push(Violation::ComponentOutOfRange {
+            id,
+            component: component as usize,
+            dim,
+        });
+    }
+}